@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS "publications" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "created_at" DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    "updated_at" DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    "ranking_id" TEXT NOT NULL,
+    "position" INTEGER NOT NULL,
+    "user_id" INTEGER NULL,
+    "text" TEXT NOT NULL,
+    "post_uri" TEXT NULL,
+    "post_cid" TEXT NULL,
+    "record_uri" TEXT NULL,
+    "record_cid" TEXT NULL,
+    "status" TEXT NOT NULL,
+    "record_rkey" TEXT NULL,
+    CONSTRAINT "publications_to_users" FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE SET NULL,
+    CONSTRAINT "publications_ranking_position" UNIQUE ("ranking_id", "position")
+);
+
+CREATE TRIGGER publications_updated_at
+AFTER
+UPDATE
+  ON "publications" BEGIN
+UPDATE
+  "publications"
+SET
+  "updated_at" = CURRENT_TIMESTAMP
+WHERE
+  "id" = NEW.id;
+
+END;
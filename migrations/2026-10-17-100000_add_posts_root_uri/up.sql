@@ -0,0 +1 @@
+ALTER TABLE "posts" ADD COLUMN "root_uri" TEXT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE "posts" DROP COLUMN "missed_refreshes";
+ALTER TABLE "posts" DROP COLUMN "refreshed_at";
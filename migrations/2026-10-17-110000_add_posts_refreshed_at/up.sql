@@ -0,0 +1,2 @@
+ALTER TABLE "posts" ADD COLUMN "refreshed_at" TIMESTAMP NULL;
+ALTER TABLE "posts" ADD COLUMN "missed_refreshes" INTEGER NOT NULL DEFAULT 0;
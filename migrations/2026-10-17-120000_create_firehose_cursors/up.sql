@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS "firehose_cursors" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "created_at" DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    "updated_at" DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    "name" TEXT NOT NULL UNIQUE,
+    "cursor" BIGINT NOT NULL
+);
+
+CREATE TRIGGER firehose_cursors_updated_at
+AFTER
+UPDATE
+  ON "firehose_cursors" BEGIN
+UPDATE
+  "firehose_cursors"
+SET
+  "updated_at" = CURRENT_TIMESTAMP
+WHERE
+  "id" = NEW.id;
+
+END;
@@ -0,0 +1,2 @@
+ALTER TABLE "users" DROP COLUMN "posts_count";
+ALTER TABLE "users" DROP COLUMN "followers_count";
@@ -0,0 +1,2 @@
+ALTER TABLE "users" ADD COLUMN "followers_count" INTEGER NULL;
+ALTER TABLE "users" ADD COLUMN "posts_count" INTEGER NULL;
@@ -0,0 +1 @@
+DROP TABLE "post_metrics_snapshots";
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS "post_metrics_snapshots" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "created_at" DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    "post_id" INTEGER NOT NULL,
+    "reply_count" INTEGER NOT NULL,
+    "repost_count" INTEGER NOT NULL,
+    "like_count" INTEGER NOT NULL,
+    "quote_count" INTEGER NOT NULL,
+    "total_points" INTEGER NOT NULL,
+    CONSTRAINT "post_metrics_snapshots_to_posts" FOREIGN KEY ("post_id") REFERENCES "posts" ("id") ON DELETE CASCADE
+);
+
+CREATE INDEX "post_metrics_snapshots_post_id" ON "post_metrics_snapshots" ("post_id");
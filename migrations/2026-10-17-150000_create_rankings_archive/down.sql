@@ -0,0 +1,3 @@
+ALTER TABLE "posts" DROP COLUMN "archived_at";
+DROP TABLE "ranking_entries";
+DROP TABLE "rankings";
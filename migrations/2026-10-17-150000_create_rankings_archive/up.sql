@@ -0,0 +1,48 @@
+CREATE TABLE IF NOT EXISTS "rankings" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "created_at" DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    "updated_at" DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    "key" TEXT NOT NULL UNIQUE,
+    "kind" TEXT NOT NULL,
+    "ranked_date" DATE NOT NULL,
+    "strategy" TEXT NOT NULL,
+    "config_hash" TEXT NOT NULL
+);
+
+CREATE TRIGGER rankings_updated_at
+AFTER
+UPDATE
+  ON "rankings" BEGIN
+UPDATE
+  "rankings"
+SET
+  "updated_at" = CURRENT_TIMESTAMP
+WHERE
+  "id" = NEW.id;
+
+END;
+
+CREATE TABLE IF NOT EXISTS "ranking_entries" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    "created_at" DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    "ranking_id" INTEGER NOT NULL,
+    "position" INTEGER NOT NULL,
+    "post_id" INTEGER NULL,
+    "user_id" INTEGER NULL,
+    "post_uri" TEXT NOT NULL,
+    "post_created_at" DATETIME NOT NULL,
+    "reply_count" INTEGER NOT NULL,
+    "repost_count" INTEGER NOT NULL,
+    "like_count" INTEGER NOT NULL,
+    "quote_count" INTEGER NOT NULL,
+    "total_points" INTEGER NOT NULL,
+    "score" DOUBLE NOT NULL,
+    CONSTRAINT "ranking_entries_to_rankings" FOREIGN KEY ("ranking_id") REFERENCES "rankings" ("id") ON DELETE CASCADE,
+    CONSTRAINT "ranking_entries_to_posts" FOREIGN KEY ("post_id") REFERENCES "posts" ("id") ON DELETE SET NULL,
+    CONSTRAINT "ranking_entries_to_users" FOREIGN KEY ("user_id") REFERENCES "users" ("id") ON DELETE SET NULL,
+    CONSTRAINT "ranking_entries_ranking_position" UNIQUE ("ranking_id", "position")
+);
+
+CREATE INDEX "ranking_entries_user_id" ON "ranking_entries" ("user_id");
+
+ALTER TABLE "posts" ADD COLUMN "archived_at" TIMESTAMP NULL;
@@ -0,0 +1,35 @@
+-- Merge duplicated posts into the oldest row, keeping the highest counters
+UPDATE
+  "posts"
+SET
+  "reply_count" = (SELECT MAX("reply_count") FROM "posts" AS "duplicate" WHERE "duplicate"."uri" = "posts"."uri"),
+  "repost_count" = (SELECT MAX("repost_count") FROM "posts" AS "duplicate" WHERE "duplicate"."uri" = "posts"."uri"),
+  "like_count" = (SELECT MAX("like_count") FROM "posts" AS "duplicate" WHERE "duplicate"."uri" = "posts"."uri"),
+  "quote_count" = (SELECT MAX("quote_count") FROM "posts" AS "duplicate" WHERE "duplicate"."uri" = "posts"."uri"),
+  "total_points" = (SELECT MAX("total_points") FROM "posts" AS "duplicate" WHERE "duplicate"."uri" = "posts"."uri"),
+  "refreshed_at" = (SELECT MAX("refreshed_at") FROM "posts" AS "duplicate" WHERE "duplicate"."uri" = "posts"."uri"),
+  "archived_at" = (SELECT MAX("archived_at") FROM "posts" AS "duplicate" WHERE "duplicate"."uri" = "posts"."uri")
+WHERE
+  "id" IN (SELECT MIN("id") FROM "posts" GROUP BY "uri" HAVING COUNT(*) > 1);
+
+UPDATE
+  "post_metrics_snapshots"
+SET
+  "post_id" = (
+    SELECT MIN("kept"."id") FROM "posts" AS "kept"
+    WHERE "kept"."uri" = (SELECT "uri" FROM "posts" WHERE "posts"."id" = "post_metrics_snapshots"."post_id")
+  );
+
+UPDATE
+  "ranking_entries"
+SET
+  "post_id" = (
+    SELECT MIN("kept"."id") FROM "posts" AS "kept"
+    WHERE "kept"."uri" = (SELECT "uri" FROM "posts" WHERE "posts"."id" = "ranking_entries"."post_id")
+  )
+WHERE
+  "post_id" IS NOT NULL;
+
+DELETE FROM "posts" WHERE "id" NOT IN (SELECT MIN("id") FROM "posts" GROUP BY "uri");
+
+CREATE UNIQUE INDEX "posts_uri" ON "posts" ("uri");
@@ -0,0 +1,249 @@
+use crate::database::models::RankingKind;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Bluesky bot publishing a daily top 10 of the community posts"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Collect and rank as usual but log posts instead of publishing them
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Save every XRPC response to the directory, with credentials redacted
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        conflicts_with = "replay_fixtures"
+    )]
+    pub record_fixtures: Option<PathBuf>,
+
+    /// Serve the XRPC responses saved by `--record-fixtures` instead of calling
+    /// Bluesky, with the clock pinned to the time of the recording
+    #[arg(long, global = true, value_name = "DIR")]
+    pub replay_fixtures: Option<PathBuf>,
+
+    /// Database of the replayed run, `replay.db` in the fixture directory by
+    /// default. `DATABASE_URL` is never used while replaying
+    #[arg(long, global = true, value_name = "PATH", requires = "replay_fixtures")]
+    pub replay_database: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Parses the process arguments. The derived parser only sees the settings
+    /// of all `backtest --variant` flags together, they are grouped here.
+    pub fn parse_args() -> Cli {
+        let matches = Cli::command().get_matches();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+        if let Some(Command::Backtest { variants, .. }) = &mut cli.command {
+            *variants = backtest_variants(&matches);
+        }
+
+        cli
+    }
+}
+
+fn backtest_variants(matches: &ArgMatches) -> Vec<Vec<String>> {
+    matches
+        .subcommand_matches("backtest")
+        .and_then(|matches| matches.get_occurrences::<String>("variant"))
+        .map(|occurrences| {
+            occurrences
+                .map(|settings| settings.cloned().collect())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start the scheduler (default when no command is given)
+    Run,
+    /// Sync followed users of the provider account
+    SyncUsers,
+    /// Collect posts of the synced users according to the collection mode
+    SyncPosts {
+        /// Age range of collected posts in hours, e.g. `24-25`
+        #[arg(long, value_parser = parse_window)]
+        window: Option<(i64, i64)>,
+    },
+    /// Refresh counters of the tracked posts without looking for new ones
+    RefreshPosts,
+    /// Count likes, reposts, replies and quotes of tracked posts from Jetstream
+    Firehose {
+        /// Read recorded events (one JSON per line) instead of connecting
+        #[arg(long)]
+        replay: Option<PathBuf>,
+        /// Count the replayed events into the tracked posts and the stored
+        /// cursor instead of only reporting them
+        #[arg(long, requires = "replay")]
+        apply: bool,
+    },
+    /// Rank collected posts and store the ranking for publishing
+    Rank {
+        /// Print the would-be top 10 without storing it
+        #[arg(long)]
+        preview: bool,
+        #[command(flatten)]
+        kind: KindArgs,
+    },
+    /// Publish the stored or freshly computed ranking
+    Publish {
+        #[command(flatten)]
+        kind: KindArgs,
+        /// Publish the authors most often in the daily top instead
+        #[arg(long, conflicts_with = "KindArgs")]
+        authors: bool,
+    },
+    /// Rank past days again with other settings and compare the results with
+    /// the configured ranking
+    Backtest {
+        /// Number of past days ranked, ending with the last complete day
+        #[arg(long, default_value_t = 7, value_parser = clap::value_parser!(i64).range(1..))]
+        days: i64,
+        /// Settings compared with the configured ones, e.g.
+        /// `--variant scoring.strategy=log ranking.max_posts_per_author=2`.
+        /// Can be repeated; every other scoring strategy is compared when none
+        /// is given
+        #[arg(id = "variant", long = "variant", value_name = "KEY=VALUE", num_args = 1..)]
+        settings: Vec<String>,
+        /// Settings of every `--variant`, filled by `Cli::parse_args`
+        #[arg(skip)]
+        variants: Vec<Vec<String>>,
+    },
+    /// Run pending database migrations
+    Migrate,
+    /// Browse archived rankings
+    Archive {
+        #[command(subcommand)]
+        command: ArchiveCommand,
+    },
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+}
+
+/// Ranking picked by the flags, the daily top when none is given
+#[derive(Args)]
+#[group(multiple = false)]
+pub struct KindArgs {
+    /// The posts gaining engagement right now
+    #[arg(long)]
+    trending: bool,
+    /// The best of the last week
+    #[arg(long)]
+    weekly: bool,
+    /// The best of the last month
+    #[arg(long)]
+    monthly: bool,
+}
+
+impl KindArgs {
+    pub fn kind(&self) -> RankingKind {
+        if self.trending {
+            RankingKind::Trending
+        } else if self.weekly {
+            RankingKind::Weekly
+        } else if self.monthly {
+            RankingKind::Monthly
+        } else {
+            RankingKind::Daily
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ArchiveCommand {
+    /// List the latest archived rankings
+    List {
+        /// Number of rankings to list
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// Print the entries of an archived ranking
+    Show {
+        /// Ranking id, as printed by `archive list`
+        key: String,
+    },
+    /// Print every archived ranking the user appeared in
+    Appearances {
+        /// Handle of the user, e.g. `bsky.one`
+        handle: String,
+    },
+    /// Print the authors most often in the daily top
+    Leaderboard {
+        /// Days of daily rankings counted, `leaderboard.days` by default
+        #[arg(long)]
+        days: Option<i64>,
+        /// Number of authors printed
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    /// Print row counts and the state of the latest publication
+    Stats,
+}
+
+fn parse_window(value: &str) -> Result<(i64, i64), String> {
+    let (start, end) = value
+        .split_once('-')
+        .ok_or_else(|| String::from("expected `<start>-<end>` in hours"))?;
+
+    let start = start
+        .trim()
+        .parse::<i64>()
+        .map_err(|err| format!("invalid start: {}", err))?;
+    let end = end
+        .trim()
+        .parse::<i64>()
+        .map_err(|err| format!("invalid end: {}", err))?;
+
+    if end <= start {
+        return Err(String::from("end of the window must be greater than start"));
+    }
+
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backtest_variants_keep_their_settings_together() {
+        let matches = Cli::command()
+            .try_get_matches_from([
+                "bsky-top10-bot",
+                "backtest",
+                "--variant",
+                "scoring.strategy=log",
+                "ranking.max_posts_per_author=2",
+                "--variant",
+                "publish.breakdown_template={likes}, {points}",
+            ])
+            .unwrap();
+
+        assert_eq!(
+            backtest_variants(&matches),
+            vec![
+                vec![
+                    String::from("scoring.strategy=log"),
+                    String::from("ranking.max_posts_per_author=2"),
+                ],
+                vec![String::from("publish.breakdown_template={likes}, {points}")],
+            ]
+        );
+    }
+}
@@ -0,0 +1,426 @@
+use chrono::{Duration, NaiveDate};
+use diesel::result::Error as DieselError;
+
+use crate::{
+    config::{Config, ScoringStrategyKind},
+    database::{
+        models::{NewPublication, Post, RankingKind, User},
+        queries,
+    },
+    ranking::{
+        backtest::{self, Comparison, DayRanking},
+        Candidate,
+    },
+    tasks::{self, Error},
+    utils::clock,
+};
+
+/// Width of the `@handle/rkey` label of a post in the backtest output
+const BACKTEST_LABEL_WIDTH: usize = 36;
+/// Width of a post with its score in the backtest output
+const BACKTEST_ENTRY_WIDTH: usize = BACKTEST_LABEL_WIDTH + 11;
+
+pub fn rank(config: &Config, kind: RankingKind, preview: bool) -> Result<(), Error> {
+    if preview {
+        let ranking = tasks::rank(config, kind)?;
+        if kind != RankingKind::Trending {
+            println!("Scoring strategy: {}", config.scoring.strategy);
+        }
+        let entries = tasks::ranking_entries(config, kind, "preview", &ranking);
+        print_ranking(&ranking, &entries);
+        return Ok(());
+    }
+
+    if let Some(ranking_id) = queries::get_unfinished_ranking_id(kind.as_str())? {
+        println!(
+            "Publication {} is not finished yet, publish it first",
+            ranking_id
+        );
+        return Ok(());
+    }
+
+    match tasks::prepare_publication(config, kind)? {
+        Some(ranking_id) => {
+            println!("Stored ranking {}", ranking_id);
+            print_publication(&ranking_id)?;
+        }
+        None => println!("There are no posts to rank"),
+    }
+
+    Ok(())
+}
+
+pub fn db_stats() -> Result<(), Error> {
+    let stats = queries::get_stats()?;
+
+    println!("Users: {}", stats.users);
+    println!(
+        "Posts: {} tracked, {} archived",
+        stats.posts, stats.archived_posts
+    );
+    println!("Rankings: {}", stats.rankings);
+    println!(
+        "Publications: {} ({} unfinished)",
+        stats.publications, stats.pending_publications
+    );
+
+    if let Some(publication) = stats.latest_publication {
+        println!(
+            "Latest publication: {} (position {}, {}, updated {})",
+            publication.ranking_id,
+            publication.position,
+            publication.status,
+            publication.updated_at.format("%d.%m.%Y %H:%M:%S"),
+        );
+    }
+
+    Ok(())
+}
+
+pub fn archive_list(limit: i64) -> Result<(), Error> {
+    let rankings = queries::get_rankings(limit)?;
+
+    if rankings.is_empty() {
+        println!("There are no archived rankings");
+        return Ok(());
+    }
+
+    for ranking in rankings {
+        println!(
+            "{}  {:<8}  {}  {}  config {}",
+            ranking.key,
+            ranking.kind,
+            ranking.ranked_date.format("%d.%m.%Y"),
+            ranking.strategy,
+            ranking.config_hash,
+        );
+    }
+
+    Ok(())
+}
+
+pub fn archive_show(key: &str) -> Result<(), Error> {
+    let Some((ranking, entries)) = queries::get_ranking(key)? else {
+        println!("Ranking {} is not archived", key);
+        return Ok(());
+    };
+
+    println!(
+        "{} ({} of {}, {}, config {})",
+        ranking.key,
+        ranking.kind,
+        ranking.ranked_date.format("%d.%m.%Y"),
+        ranking.strategy,
+        ranking.config_hash,
+    );
+
+    for (entry, user) in entries {
+        let handle = user
+            .map(|user| format!("@{}", user.handle))
+            .unwrap_or_else(|| String::from("(unknown user)"));
+        println!(
+            "{:>2}. {:>8.2} pts  {}  {}\n    {} likes, {} replies, {} reposts, {} quotes, {} points",
+            entry.position,
+            entry.score,
+            handle,
+            entry.post_uri,
+            entry.like_count,
+            entry.reply_count,
+            entry.repost_count,
+            entry.quote_count,
+            entry.total_points,
+        );
+    }
+
+    Ok(())
+}
+
+pub fn archive_appearances(handle: &str) -> Result<(), Error> {
+    let Some(user) = queries::get_user_by_handle(handle)? else {
+        println!("User {} is not followed", handle);
+        return Ok(());
+    };
+
+    let appearances = queries::get_user_appearances(user.id)?;
+
+    let kinds = [
+        RankingKind::Daily,
+        RankingKind::Trending,
+        RankingKind::Weekly,
+        RankingKind::Monthly,
+    ];
+    let counts: Vec<String> = kinds
+        .iter()
+        .map(|kind| {
+            let count = appearances
+                .iter()
+                .filter(|(_, ranking)| ranking.kind == kind.as_str())
+                .count();
+            format!("{} {}", count, kind.as_str())
+        })
+        .collect();
+    println!("@{} appeared in {}", user.handle, counts.join(", "));
+
+    for (entry, ranking) in appearances {
+        println!(
+            "{}  {:<8}  #{:<2}  {:>8.2} pts  {}",
+            ranking.ranked_date.format("%d.%m.%Y"),
+            ranking.kind,
+            entry.position,
+            entry.score,
+            entry.post_uri,
+        );
+    }
+
+    Ok(())
+}
+
+pub fn archive_leaderboard(days: i64, limit: usize) -> Result<(), Error> {
+    let since = (clock::now() - Duration::days(days)).date_naive();
+    let leaderboard = queries::get_author_leaderboard(since)?;
+
+    if leaderboard.is_empty() {
+        println!("Nobody was ranked since {}", since.format("%d.%m.%Y"));
+        return Ok(());
+    }
+
+    println!("Daily top authors since {}", since.format("%d.%m.%Y"));
+    for (position, stats) in leaderboard.iter().take(limit).enumerate() {
+        println!(
+            "{:>2}. @{}  {} times, best #{}, streak {}, {} points",
+            position + 1,
+            stats.user.handle,
+            stats.appearances,
+            stats.best_position,
+            stats.current_streak,
+            stats.total_points,
+        );
+    }
+
+    Ok(())
+}
+
+pub fn backtest(config: &Config, days: i64, variants: &[Vec<String>]) -> Result<(), Error> {
+    // Without variants every other strategy is compared with the same weights
+    let variants: Vec<Vec<String>> = if variants.is_empty() {
+        ScoringStrategyKind::ALL
+            .iter()
+            .filter(|kind| **kind != config.scoring.strategy)
+            .map(|kind| vec![format!("scoring.strategy={}", kind)])
+            .collect()
+    } else {
+        variants.to_vec()
+    };
+
+    let mut configs: Vec<(String, Config)> = Vec::new();
+    for variant in variants {
+        let variant_config = config.with_overrides(&variant)?;
+        configs.push((variant.join(" "), variant_config));
+    }
+
+    let baseline = format!("configured ({})", config.scoring.strategy);
+    println!(
+        "Days run from 00:00 to 24:00 UTC, while the daily ranking takes posts {} to {} hours old \
+         when it runs, so the tops can differ from the published ones, which are compared when archived.",
+        config.collection.window_start_hours, config.collection.window_end_hours,
+    );
+    let last_day = (clock::now() - Duration::hours(config.collection.window_start_hours))
+        .date_naive()
+        - Duration::days(1);
+    let mut comparisons: Vec<Vec<Comparison>> = configs.iter().map(|_| Vec::new()).collect();
+
+    for offset in (0..days).rev() {
+        let day = last_day - Duration::days(offset);
+        let posts_with_users = day_posts(day)?;
+
+        println!();
+        if posts_with_users.is_empty() {
+            println!("{}: no posts", day.format("%d.%m.%Y"));
+            continue;
+        }
+        println!(
+            "{}: {} posts",
+            day.format("%d.%m.%Y"),
+            posts_with_users.len()
+        );
+
+        let base = backtest::rank_day(config, posts_with_users.clone());
+        match queries::get_archived_ranking_uris(RankingKind::Daily.as_str(), day)? {
+            Some(published) => {
+                let comparison = backtest::compare_published(&base, &published);
+                println!(
+                    "  {} vs published: {}",
+                    baseline,
+                    format_comparison(&comparison, published.len())
+                );
+            }
+            None => println!("  {} vs published: not archived", baseline),
+        }
+        for ((name, variant_config), variant_comparisons) in
+            configs.iter().zip(comparisons.iter_mut())
+        {
+            let other = backtest::rank_day(variant_config, posts_with_users.clone());
+            let comparison = backtest::compare(&base, &other);
+
+            println!(
+                "  {} vs {}: {}",
+                baseline,
+                name,
+                format_comparison(&comparison, base.top.len())
+            );
+            print_side_by_side(&baseline, &base, name, &other);
+
+            variant_comparisons.push(comparison);
+        }
+    }
+
+    println!();
+    println!("Summary of {} days against {}", days, baseline);
+    for ((name, _), variant_comparisons) in configs.iter().zip(comparisons.iter()) {
+        if variant_comparisons.is_empty() {
+            println!("  {}: no ranked days", name);
+            continue;
+        }
+
+        let ranked_days = variant_comparisons.len() as f64;
+        let overlap = variant_comparisons
+            .iter()
+            .map(|comparison| comparison.overlap as f64)
+            .sum::<f64>()
+            / ranked_days;
+
+        println!(
+            "  {}: {:.1} posts in both tops, mean rho {}, mean tau {} over {} days",
+            name,
+            overlap,
+            format_statistic(mean(variant_comparisons.iter().map(|c| c.spearman))),
+            format_statistic(mean(variant_comparisons.iter().map(|c| c.kendall))),
+            variant_comparisons.len(),
+        );
+    }
+    Ok(())
+}
+
+/// Posts created on the day, archived ones included
+fn day_posts(day: NaiveDate) -> Result<Vec<(Post, User)>, DieselError> {
+    let since = day.and_hms_opt(0, 0, 0).unwrap();
+    queries::get_period_candidates(since, since + Duration::days(1))
+}
+
+fn print_side_by_side(base_name: &str, base: &DayRanking, other_name: &str, other: &DayRanking) {
+    println!(
+        "      {:<width$}  {}",
+        base_name,
+        other_name,
+        width = BACKTEST_ENTRY_WIDTH
+    );
+
+    for position in 0..base.top.len().max(other.top.len()) {
+        let left = base
+            .top
+            .get(position)
+            .map(format_backtest_entry)
+            .unwrap_or_default();
+        let right = other
+            .top
+            .get(position)
+            .map(|candidate| {
+                let movement = base
+                    .top
+                    .iter()
+                    .position(|entry| entry.post.uri == candidate.post.uri)
+                    .map(
+                        |base_position| match base_position as i64 - position as i64 {
+                            0 => String::from("="),
+                            shift if shift > 0 => format!("+{}", shift),
+                            shift => shift.to_string(),
+                        },
+                    )
+                    .unwrap_or_else(|| String::from("new"));
+                format!("{}  {}", format_backtest_entry(candidate), movement)
+            })
+            .unwrap_or_default();
+
+        println!(
+            "  {:>2}. {:<width$}  {}",
+            position + 1,
+            left,
+            right,
+            width = BACKTEST_ENTRY_WIDTH
+        );
+    }
+}
+
+/// `@handle/rkey` of the post with its score
+fn format_backtest_entry(candidate: &Candidate) -> String {
+    let rkey = candidate.post.uri.rsplit('/').next().unwrap_or_default();
+    let label: String = format!("@{}/{}", candidate.user.handle, rkey)
+        .chars()
+        .take(BACKTEST_LABEL_WIDTH)
+        .collect();
+
+    format!(
+        "{:<width$} {:>10.2}",
+        label,
+        candidate.score,
+        width = BACKTEST_LABEL_WIDTH
+    )
+}
+
+fn format_comparison(comparison: &Comparison, size: usize) -> String {
+    format!(
+        "{}/{} in both tops, rho {}, tau {}",
+        comparison.overlap,
+        size,
+        format_statistic(comparison.spearman),
+        format_statistic(comparison.kendall),
+    )
+}
+
+fn format_statistic(value: Option<f64>) -> String {
+    value
+        .map(|value| format!("{:.2}", value))
+        .unwrap_or_else(|| String::from("n/a"))
+}
+
+fn mean(values: impl Iterator<Item = Option<f64>>) -> Option<f64> {
+    let values: Vec<f64> = values.flatten().collect();
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn print_ranking(ranking: &[Candidate], entries: &[NewPublication]) {
+    if ranking.is_empty() {
+        println!("There are no posts to rank");
+        return;
+    }
+
+    for entry in entries {
+        let Some(index) = (entry.position as usize).checked_sub(1) else {
+            println!("{}", entry.text);
+            continue;
+        };
+
+        let Candidate { post, user, score } = &ranking[index];
+        println!(
+            "{:>2}. {:>8.2} pts  @{}  {}\n    {}",
+            entry.position, score, user.handle, post.uri, entry.text,
+        );
+    }
+}
+
+fn print_publication(ranking_id: &str) -> Result<(), Error> {
+    for (entry, _) in queries::get_publication_entries(ranking_id)? {
+        println!(
+            "{:>2}. [{}] {}{}",
+            entry.position,
+            entry.status,
+            entry.text,
+            entry
+                .post_uri
+                .map(|uri| format!("\n    {}", uri))
+                .unwrap_or_default(),
+        );
+    }
+
+    Ok(())
+}
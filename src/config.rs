@@ -0,0 +1,918 @@
+use crate::services::bsky::{DEFAULT_APPVIEW, DEFAULT_ENTRYWAY};
+use cron::Schedule;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{env, fmt, fs, io, path::PathBuf, str::FromStr};
+use toml::{Table, Value};
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+const ENV_PREFIX: &str = "TOP10_";
+
+/// Days ranked by the weekly and the monthly rankings
+pub const WEEKLY_PERIOD_DAYS: i64 = 7;
+pub const MONTHLY_PERIOD_DAYS: i64 = 30;
+/// Largest scoring weight, points are counted in `i32`
+pub const MAX_SCORING_WEIGHT: i32 = 1000;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub provider: ProviderConfig,
+    pub bsky: BskyConfig,
+    pub collection: CollectionConfig,
+    pub scoring: ScoringConfig,
+    pub ranking: RankingConfig,
+    pub schedule: ScheduleConfig,
+    pub publish: PublishConfig,
+    pub firehose: FirehoseConfig,
+    pub trending: TrendingConfig,
+    #[serde(
+        default = "PeriodRankingConfig::weekly",
+        deserialize_with = "weekly_section"
+    )]
+    pub weekly: PeriodRankingConfig,
+    #[serde(
+        default = "PeriodRankingConfig::monthly",
+        deserialize_with = "monthly_section"
+    )]
+    pub monthly: PeriodRankingConfig,
+    pub leaderboard: LeaderboardConfig,
+    pub archive: ArchiveConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderConfig {
+    /// Account whose follows are treated as the ranked community
+    pub account: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct BskyConfig {
+    /// Host the session is created and refreshed on
+    pub entryway: String,
+    /// Host of the posts, resolved from the DID document of the account when
+    /// not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pds: Option<String>,
+    /// Host of the public read endpoints (follows, feeds, posts, profiles)
+    pub appview: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectionConfig {
+    /// Youngest age of a collected post, in hours
+    pub window_start_hours: i64,
+    /// Oldest age of a collected post, in hours
+    pub window_end_hours: i64,
+    /// Number of author feeds crawled at the same time
+    pub concurrency: usize,
+    /// Upper bound of requests to Bsky per second, 0 disables the limit
+    pub max_requests_per_second: f64,
+    pub mode: CollectionMode,
+    /// In refresh mode, age of the oldest post looked up in author feeds, in
+    /// hours. Should cover the interval between two collections.
+    pub discovery_hours: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionMode {
+    /// Crawl author feeds once posts reach the window and sample their counts
+    #[default]
+    Feed,
+    /// Track fresh posts and refresh their counts until they leave the window.
+    /// New posts are still looked up in every author feed, but only the
+    /// first `discovery_hours` of it.
+    Refresh,
+}
+
+impl fmt::Display for CollectionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollectionMode::Feed => write!(f, "feed"),
+            CollectionMode::Refresh => write!(f, "refresh"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FirehoseConfig {
+    /// Count engagement from Jetstream while the scheduler runs
+    pub enabled: bool,
+    /// Jetstream subscribe endpoint
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringConfig {
+    pub strategy: ScoringStrategyKind,
+    pub like_weight: i32,
+    pub reply_weight: i32,
+    pub repost_weight: i32,
+    pub quote_weight: i32,
+    /// Exponent of the post age in the `time_decay` strategy
+    pub gravity: f64,
+    /// Imaginary average posts added to each author in the `engagement_rate`
+    /// strategy, the higher it is the less a single post stands out
+    pub baseline_prior_posts: f64,
+    /// Smallest follower count the `audience` strategy divides by
+    pub audience_floor: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoringStrategyKind {
+    #[default]
+    WeightedSum,
+    TimeDecay,
+    EngagementRate,
+    Log,
+    Audience,
+}
+
+impl ScoringStrategyKind {
+    pub const ALL: [ScoringStrategyKind; 5] = [
+        ScoringStrategyKind::WeightedSum,
+        ScoringStrategyKind::TimeDecay,
+        ScoringStrategyKind::EngagementRate,
+        ScoringStrategyKind::Log,
+        ScoringStrategyKind::Audience,
+    ];
+}
+
+impl fmt::Display for ScoringStrategyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScoringStrategyKind::WeightedSum => write!(f, "weighted_sum"),
+            ScoringStrategyKind::TimeDecay => write!(f, "time_decay"),
+            ScoringStrategyKind::EngagementRate => write!(f, "engagement_rate"),
+            ScoringStrategyKind::Log => write!(f, "log"),
+            ScoringStrategyKind::Audience => write!(f, "audience"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RankingConfig {
+    /// Number of entries in the published ranking
+    pub size: usize,
+    pub max_posts_per_author: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// Cron expression of the daily ranking publication
+    pub publish: String,
+    /// Cron expression of the posts collection
+    pub collect: String,
+    /// Cron expression of the trending list publication
+    pub trending: String,
+    /// Cron expression of the best of the week publication
+    pub weekly: String,
+    /// Cron expression of the best of the month publication
+    pub monthly: String,
+    /// Cron expression of the top authors publication
+    pub leaderboard: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrendingConfig {
+    /// Snapshot counters of young posts and publish the trending list
+    pub enabled: bool,
+    /// Number of entries in the trending list
+    pub size: usize,
+    /// Oldest age of a trending post, in hours. Counters of younger posts are
+    /// snapshotted on every refresh.
+    pub max_age_hours: i64,
+    /// Engagement growth is measured over this many last hours
+    pub lookback_hours: i64,
+    /// Text of the thread root, `{date}` and `{time}` are replaced with the
+    /// time of the ranking
+    pub header_template: String,
+    /// Text every trending entry starts with
+    pub message_prefix: String,
+}
+
+/// Best of the posts stored over the last 7 (weekly) or 30 (monthly) days.
+/// Each period has its own defaults, see `PeriodRankingSection`.
+#[derive(Serialize, Clone, Debug)]
+pub struct PeriodRankingConfig {
+    /// Publish the ranking on its schedule
+    pub enabled: bool,
+    /// Number of entries in the ranking
+    pub size: usize,
+    /// Rank authors by the sum of the scores of their posts instead of posts
+    pub rank_authors: bool,
+    /// Text of the thread root, `{start}` and `{end}` are replaced with the
+    /// first and the last ranked day
+    pub header_template: String,
+    /// Text every entry starts with
+    pub message_prefix: String,
+}
+
+/// `[weekly]` or `[monthly]` section as written, settings left out take the
+/// defaults of the period
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PeriodRankingSection {
+    enabled: Option<bool>,
+    size: Option<usize>,
+    rank_authors: Option<bool>,
+    header_template: Option<String>,
+    message_prefix: Option<String>,
+}
+
+impl PeriodRankingSection {
+    fn or_defaults(self, defaults: PeriodRankingConfig) -> PeriodRankingConfig {
+        PeriodRankingConfig {
+            enabled: self.enabled.unwrap_or(defaults.enabled),
+            size: self.size.unwrap_or(defaults.size),
+            rank_authors: self.rank_authors.unwrap_or(defaults.rank_authors),
+            header_template: self.header_template.unwrap_or(defaults.header_template),
+            message_prefix: self.message_prefix.unwrap_or(defaults.message_prefix),
+        }
+    }
+}
+
+fn weekly_section<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PeriodRankingConfig, D::Error> {
+    Ok(PeriodRankingSection::deserialize(deserializer)?.or_defaults(PeriodRankingConfig::weekly()))
+}
+
+fn monthly_section<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PeriodRankingConfig, D::Error> {
+    Ok(
+        PeriodRankingSection::deserialize(deserializer)?
+            .or_defaults(PeriodRankingConfig::monthly()),
+    )
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaderboardConfig {
+    /// Publish the top authors of the daily rankings on its schedule
+    pub enabled: bool,
+    /// Days of daily rankings counted
+    pub days: i64,
+    /// Number of authors in the publication
+    pub size: usize,
+    /// Text of the thread root, `{start}` and `{end}` are replaced with the
+    /// first and the last counted day
+    pub header_template: String,
+    /// Text of every author entry. `{position}`, `{handle}`, `{name}`,
+    /// `{appearances}`, `{best_position}`, `{streak}` and `{points}` are
+    /// replaced, `@{handle}` becomes a mention.
+    pub entry_template: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    /// Days ranked posts are kept in the archive, 0 keeps them forever
+    pub posts_retention_days: i64,
+    /// Days published rankings are kept, 0 keeps them forever
+    pub rankings_retention_days: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PublishConfig {
+    /// Text of the thread root, `{date}` is replaced with the ranked day
+    pub header_template: String,
+    /// Text every ranking entry starts with
+    pub message_prefix: String,
+    /// Line added under the author of every ranked post. `{position}`,
+    /// `{size}`, `{likes}`, `{replies}`, `{reposts}`, `{quotes}` and `{points}`
+    /// are replaced with the place in the ranking and the stored counters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakdown_template: Option<String>,
+    /// Delay between posts of the thread, in seconds
+    pub delay_secs: u64,
+    pub lang: String,
+    /// Log `createRecord` payloads instead of posting them
+    pub dry_run: bool,
+    /// File the dry run payloads are appended to, stdout when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run_output: Option<String>,
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        ProviderConfig {
+            account: String::from("bsky.one"),
+        }
+    }
+}
+
+impl Default for BskyConfig {
+    fn default() -> Self {
+        BskyConfig {
+            entryway: String::from(DEFAULT_ENTRYWAY),
+            pds: None,
+            appview: String::from(DEFAULT_APPVIEW),
+        }
+    }
+}
+
+impl Default for CollectionConfig {
+    fn default() -> Self {
+        CollectionConfig {
+            window_start_hours: 24,
+            window_end_hours: 25,
+            concurrency: 10,
+            max_requests_per_second: 0.0,
+            mode: CollectionMode::Feed,
+            discovery_hours: 2,
+        }
+    }
+}
+
+impl Default for FirehoseConfig {
+    fn default() -> Self {
+        FirehoseConfig {
+            enabled: false,
+            url: String::from("wss://jetstream2.us-east.bsky.network/subscribe"),
+        }
+    }
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        ScoringConfig {
+            strategy: ScoringStrategyKind::WeightedSum,
+            like_weight: 1,
+            reply_weight: 5,
+            repost_weight: 3,
+            quote_weight: 4,
+            gravity: 1.8,
+            baseline_prior_posts: 3.0,
+            audience_floor: 100,
+        }
+    }
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        RankingConfig {
+            size: 10,
+            max_posts_per_author: 1,
+        }
+    }
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            publish: String::from("0 0 12 */1 * *"),
+            collect: String::from("0 0 */1 * * *"),
+            trending: String::from("0 30 */3 * * *"),
+            weekly: String::from("0 0 13 * * Mon"),
+            monthly: String::from("0 0 14 1 * *"),
+            leaderboard: String::from("0 0 15 1 * *"),
+        }
+    }
+}
+
+impl Default for TrendingConfig {
+    fn default() -> Self {
+        TrendingConfig {
+            enabled: false,
+            size: 5,
+            max_age_hours: 6,
+            lookback_hours: 2,
+            header_template: String::from("#Тренди {date} {time}"),
+            message_prefix: String::from("#Тренди"),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            provider: ProviderConfig::default(),
+            bsky: BskyConfig::default(),
+            collection: CollectionConfig::default(),
+            scoring: ScoringConfig::default(),
+            ranking: RankingConfig::default(),
+            schedule: ScheduleConfig::default(),
+            publish: PublishConfig::default(),
+            firehose: FirehoseConfig::default(),
+            trending: TrendingConfig::default(),
+            weekly: PeriodRankingConfig::weekly(),
+            monthly: PeriodRankingConfig::monthly(),
+            leaderboard: LeaderboardConfig::default(),
+            archive: ArchiveConfig::default(),
+        }
+    }
+}
+
+impl PeriodRankingConfig {
+    pub fn weekly() -> Self {
+        PeriodRankingConfig {
+            enabled: false,
+            size: 10,
+            rank_authors: false,
+            header_template: String::from("#Топ тижня {start} – {end}"),
+            message_prefix: String::from("#ТопТижня"),
+        }
+    }
+
+    /// Ranks authors, a month gives most of them several posts
+    pub fn monthly() -> Self {
+        PeriodRankingConfig {
+            enabled: false,
+            size: 10,
+            rank_authors: true,
+            header_template: String::from("#Топ місяця {start} – {end}"),
+            message_prefix: String::from("#ТопМісяця"),
+        }
+    }
+}
+
+impl Default for LeaderboardConfig {
+    fn default() -> Self {
+        LeaderboardConfig {
+            enabled: false,
+            days: 30,
+            size: 10,
+            header_template: String::from("#ТопАвторів за {start} – {end}"),
+            entry_template: String::from(
+                "{position}. @{handle}: у топі {appearances} раз(и), найкраще місце {best_position}, балів: {points}",
+            ),
+        }
+    }
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        ArchiveConfig {
+            posts_retention_days: 30,
+            rankings_retention_days: 0,
+        }
+    }
+}
+
+impl Default for PublishConfig {
+    fn default() -> Self {
+        PublishConfig {
+            header_template: String::from("#Топ10 постів за {date}"),
+            message_prefix: String::from("#Топ10"),
+            breakdown_template: None,
+            delay_secs: 300,
+            lang: String::from("ua"),
+            dry_run: false,
+            dry_run_output: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Env(String, String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => {
+                write!(f, "Unable to read config {}: {}", path.display(), err)
+            }
+            ConfigError::Parse(path, err) => {
+                write!(f, "Unable to parse config {}: {}", path.display(), err)
+            }
+            ConfigError::Env(key, err) => write!(f, "Invalid value of {}: {}", key, err),
+            ConfigError::Invalid(message) => write!(f, "Invalid config: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads the file from `CONFIG_PATH` (or `config.toml` when present), applies
+    /// environment overrides and validates the result.
+    ///
+    /// Any key can be overridden with `TOP10_<SECTION>__<KEY>`, e.g.
+    /// `TOP10_SCORING__LIKE_WEIGHT=2`. `ACCOUNT_PROVIDER` and `MAX_POSTS_PER_AUTHOR`
+    /// are kept as shortcuts.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut table = match env::var("CONFIG_PATH") {
+            Ok(path) => read_table(PathBuf::from(path))?,
+            Err(_) => {
+                let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+                if path.exists() {
+                    read_table(path)?
+                } else {
+                    Table::new()
+                }
+            }
+        };
+
+        // Values are typed like the setting they override
+        let defaults = match Value::try_from(Config::default()) {
+            Ok(Value::Table(defaults)) => defaults,
+            _ => return Err(invalid("config is not serializable")),
+        };
+
+        if let Ok(value) = env::var("ACCOUNT_PROVIDER") {
+            set_override(&mut table, "provider__account", Value::String(value))?;
+        }
+        if let Ok(value) = env::var("MAX_POSTS_PER_AUTHOR") {
+            let path = "ranking__max_posts_per_author";
+            set_override(&mut table, path, typed_value(&defaults, path, &value))?;
+        }
+
+        let mut overrides: Vec<(String, String)> = env::vars()
+            .filter_map(|(key, value)| {
+                let path = key.strip_prefix(ENV_PREFIX)?.to_lowercase();
+                Some((path, value))
+            })
+            .collect();
+        overrides.sort();
+
+        for (path, value) in overrides {
+            let value = typed_value(&defaults, &path, &value);
+            set_override(&mut table, &path, value)?;
+        }
+
+        let config: Config = Value::Table(table)
+            .try_into()
+            .map_err(|err: toml::de::Error| ConfigError::Invalid(err.message().to_string()))?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.provider.account.trim().is_empty() {
+            return Err(invalid("provider.account must not be empty"));
+        }
+
+        let hosts = [
+            ("bsky.entryway", Some(&self.bsky.entryway)),
+            ("bsky.pds", self.bsky.pds.as_ref()),
+            ("bsky.appview", Some(&self.bsky.appview)),
+        ];
+        for (name, host) in hosts {
+            let Some(host) = host else {
+                continue;
+            };
+            if !host.starts_with("http://") && !host.starts_with("https://") {
+                return Err(invalid(&format!(
+                    "{} must be an http:// or https:// url",
+                    name
+                )));
+            }
+        }
+
+        if self.collection.window_start_hours < 0 {
+            return Err(invalid(
+                "collection.window_start_hours must not be negative",
+            ));
+        }
+        if self.collection.window_end_hours <= self.collection.window_start_hours {
+            return Err(invalid(
+                "collection.window_end_hours must be greater than collection.window_start_hours",
+            ));
+        }
+
+        if self.collection.discovery_hours <= 0 {
+            return Err(invalid("collection.discovery_hours must be greater than 0"));
+        }
+
+        if self.collection.concurrency == 0 {
+            return Err(invalid("collection.concurrency must be greater than 0"));
+        }
+        let rate = self.collection.max_requests_per_second;
+        if !rate.is_finite() || rate < 0.0 {
+            return Err(invalid(
+                "collection.max_requests_per_second must not be negative",
+            ));
+        }
+
+        let weights = [
+            ("like_weight", self.scoring.like_weight),
+            ("reply_weight", self.scoring.reply_weight),
+            ("repost_weight", self.scoring.repost_weight),
+            ("quote_weight", self.scoring.quote_weight),
+        ];
+        for (name, weight) in weights {
+            if !(0..=MAX_SCORING_WEIGHT).contains(&weight) {
+                return Err(invalid(&format!(
+                    "scoring.{} must be between 0 and {}",
+                    name, MAX_SCORING_WEIGHT
+                )));
+            }
+        }
+
+        let gravity = self.scoring.gravity;
+        if !gravity.is_finite() || gravity < 0.0 {
+            return Err(invalid("scoring.gravity must not be negative"));
+        }
+        let prior_posts = self.scoring.baseline_prior_posts;
+        if !prior_posts.is_finite() || prior_posts <= 0.0 {
+            return Err(invalid(
+                "scoring.baseline_prior_posts must be greater than 0",
+            ));
+        }
+        if self.scoring.audience_floor <= 0 {
+            return Err(invalid("scoring.audience_floor must be greater than 0"));
+        }
+
+        if self.ranking.size == 0 {
+            return Err(invalid("ranking.size must be greater than 0"));
+        }
+        if self.ranking.max_posts_per_author == 0 {
+            return Err(invalid(
+                "ranking.max_posts_per_author must be greater than 0",
+            ));
+        }
+
+        validate_cron("schedule.publish", &self.schedule.publish)?;
+        validate_cron("schedule.collect", &self.schedule.collect)?;
+        validate_cron("schedule.trending", &self.schedule.trending)?;
+        validate_cron("schedule.weekly", &self.schedule.weekly)?;
+        validate_cron("schedule.monthly", &self.schedule.monthly)?;
+        validate_cron("schedule.leaderboard", &self.schedule.leaderboard)?;
+
+        if self.publish.lang.trim().is_empty() {
+            return Err(invalid("publish.lang must not be empty"));
+        }
+
+        if let Some(template) = &self.publish.breakdown_template {
+            if template.trim().is_empty() {
+                return Err(invalid(
+                    "publish.breakdown_template must not be empty, leave it out to disable it",
+                ));
+            }
+        }
+
+        if !self.firehose.url.starts_with("ws://") && !self.firehose.url.starts_with("wss://") {
+            return Err(invalid("firehose.url must be a ws:// or wss:// url"));
+        }
+
+        if self.trending.enabled && self.collection.mode != CollectionMode::Refresh {
+            return Err(invalid(
+                "trending.enabled needs collection.mode = \"refresh\" to snapshot young posts",
+            ));
+        }
+        if self.trending.size == 0 {
+            return Err(invalid("trending.size must be greater than 0"));
+        }
+        if self.trending.lookback_hours <= 0 {
+            return Err(invalid("trending.lookback_hours must be greater than 0"));
+        }
+        if self.trending.max_age_hours < self.trending.lookback_hours {
+            return Err(invalid(
+                "trending.max_age_hours must not be less than trending.lookback_hours",
+            ));
+        }
+
+        let periods = [
+            ("weekly", &self.weekly, WEEKLY_PERIOD_DAYS),
+            ("monthly", &self.monthly, MONTHLY_PERIOD_DAYS),
+        ];
+        for (name, period, days) in periods {
+            if period.size == 0 {
+                return Err(invalid(&format!("{}.size must be greater than 0", name)));
+            }
+
+            let retention = self.archive.posts_retention_days;
+            if period.enabled && retention > 0 && retention < days {
+                return Err(invalid(&format!(
+                    "archive.posts_retention_days must be at least {} to rank the {} period",
+                    days, name
+                )));
+            }
+        }
+
+        if self.leaderboard.days <= 0 {
+            return Err(invalid("leaderboard.days must be greater than 0"));
+        }
+        if self.leaderboard.size == 0 {
+            return Err(invalid("leaderboard.size must be greater than 0"));
+        }
+        let retention = self.archive.rankings_retention_days;
+        if self.leaderboard.enabled && retention > 0 && retention < self.leaderboard.days {
+            return Err(invalid(
+                "archive.rankings_retention_days must not be less than leaderboard.days",
+            ));
+        }
+
+        if self.archive.posts_retention_days < 0 {
+            return Err(invalid("archive.posts_retention_days must not be negative"));
+        }
+        if self.archive.rankings_retention_days < 0 {
+            return Err(invalid(
+                "archive.rankings_retention_days must not be negative",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Copy of the config with `section.key=value` overrides applied, e.g.
+    /// `scoring.strategy=log`. Everything after the first `=` is the value.
+    pub fn with_overrides(&self, overrides: &[String]) -> Result<Config, ConfigError> {
+        let mut table = match Value::try_from(self) {
+            Ok(Value::Table(table)) => table,
+            _ => return Err(invalid("config is not serializable")),
+        };
+        let current = table.clone();
+
+        for setting in overrides {
+            let Some((key, value)) = setting.split_once('=') else {
+                return Err(invalid(&format!(
+                    "expected `section.key=value`, got `{}`",
+                    setting
+                )));
+            };
+
+            let path = key.trim().replace('.', "__");
+            let value = typed_value(&current, &path, value);
+            set_override(&mut table, &path, value)
+                .map_err(|_| invalid(&format!("{} is not a setting", key.trim())))?;
+        }
+
+        let config: Config = Value::Table(table)
+            .try_into()
+            .map_err(|err: toml::de::Error| ConfigError::Invalid(err.message().to_string()))?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Hash of the settings that decide a ranking, stored with archived
+    /// rankings to tell apart results of different configurations.
+    pub fn fingerprint(&self) -> String {
+        let settings = (
+            &self.scoring,
+            &self.ranking,
+            &self.trending,
+            &self.weekly,
+            &self.monthly,
+        );
+        let serialized = serde_json::to_string(&settings).unwrap_or_default();
+
+        format!("{:016x}", fnv1a(serialized.as_bytes()))
+    }
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn read_table(path: PathBuf) -> Result<Table, ConfigError> {
+    let content = fs::read_to_string(&path).map_err(|err| ConfigError::Io(path.clone(), err))?;
+
+    content
+        .parse::<Table>()
+        .map_err(|err| ConfigError::Parse(path, err))
+}
+
+/// Writes `value` to the `section__key` path of the table.
+fn set_override(table: &mut Table, path: &str, value: Value) -> Result<(), ConfigError> {
+    let keys: Vec<&str> = path.split("__").collect();
+    let (last, sections) = keys.split_last().unwrap();
+
+    let mut current = table;
+    for section in sections {
+        let entry = current
+            .entry(section.to_string())
+            .or_insert_with(|| Value::Table(Table::new()));
+
+        current = match entry {
+            Value::Table(table) => table,
+            _ => {
+                return Err(ConfigError::Env(
+                    format!("{}{}", ENV_PREFIX, path.to_uppercase()),
+                    format!("{} is not a section", section),
+                ))
+            }
+        };
+    }
+
+    current.insert(last.to_string(), value);
+
+    Ok(())
+}
+
+/// Types an override like the setting at the `section__key` path of the
+/// reference table. Text settings, and optional ones left out of it, take the
+/// value verbatim, others parse it as a TOML scalar.
+fn typed_value(reference: &Table, path: &str, value: &str) -> Value {
+    let mut keys = path.split("__");
+    let mut setting = keys.next().and_then(|key| reference.get(key));
+    for key in keys {
+        setting = setting.and_then(|current| current.get(key));
+    }
+
+    match setting {
+        None | Some(Value::String(_)) => Value::String(value.to_string()),
+        Some(_) => parse_scalar(value.trim()),
+    }
+}
+
+/// Types a value as a TOML scalar when it parses as one and as a plain string
+/// otherwise.
+fn parse_scalar(value: &str) -> Value {
+    format!("value = {}", value)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+fn validate_cron(name: &str, expression: &str) -> Result<(), ConfigError> {
+    Schedule::from_str(expression)
+        .map(|_| ())
+        .map_err(|err| invalid(&format!("{} is not a valid cron expression: {}", name, err)))
+}
+
+fn invalid(message: &str) -> ConfigError {
+    ConfigError::Invalid(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Table {
+        match Value::try_from(Config::default()) {
+            Ok(Value::Table(table)) => table,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn overrides_are_typed_like_their_setting() {
+        let defaults = defaults();
+
+        assert_eq!(
+            typed_value(&defaults, "provider__account", "123"),
+            Value::String(String::from("123"))
+        );
+        assert_eq!(
+            typed_value(
+                &defaults,
+                "publish__breakdown_template",
+                "{likes}, {points}"
+            ),
+            Value::String(String::from("{likes}, {points}"))
+        );
+        assert_eq!(
+            typed_value(&defaults, "scoring__like_weight", " 2"),
+            Value::Integer(2)
+        );
+        assert_eq!(
+            typed_value(&defaults, "trending__enabled", "true"),
+            Value::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn overrides_keep_commas_of_values() {
+        let config = Config::default()
+            .with_overrides(&[
+                String::from("weekly.header_template=#Топ тижня, {start} – {end}"),
+                String::from("ranking.size=5"),
+            ])
+            .unwrap();
+
+        assert_eq!(config.weekly.header_template, "#Топ тижня, {start} – {end}");
+        assert_eq!(config.ranking.size, 5);
+        assert!(Config::default()
+            .with_overrides(&[String::from("ranking.size")])
+            .is_err());
+    }
+
+    #[test]
+    fn scoring_weights_are_bounded() {
+        for weight in [-1, MAX_SCORING_WEIGHT + 1] {
+            let mut config = Config::default();
+            config.scoring.quote_weight = weight;
+            assert!(config.validate().is_err(), "weight {} is accepted", weight);
+        }
+
+        let mut config = Config::default();
+        config.scoring.quote_weight = MAX_SCORING_WEIGHT;
+        assert!(config.validate().is_ok());
+    }
+}
@@ -8,3 +8,10 @@ pub fn migrate() {
 
     conn.run_pending_migrations(MIGRATIONS).unwrap();
 }
+
+/// Whether the database is behind the migrations embedded in the binary
+pub fn has_pending() -> bool {
+    let mut conn = establish_connection();
+
+    conn.has_pending_migration(MIGRATIONS).unwrap()
+}
@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
 
 #[derive(Queryable, Selectable, Clone, Debug)]
@@ -11,6 +11,8 @@ pub struct User {
     pub did: String,
     pub handle: String,
     pub display_name: Option<String>,
+    pub followers_count: Option<i32>,
+    pub posts_count: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -21,7 +23,8 @@ pub struct NewUser {
     pub display_name: Option<String>,
 }
 
-#[derive(Queryable, Selectable, Associations, Debug)]
+#[derive(Queryable, Selectable, Associations, Clone, Debug)]
+#[allow(dead_code)]
 #[diesel(table_name = super::schema::posts)]
 #[diesel(belongs_to(User))]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -36,6 +39,11 @@ pub struct Post {
     pub like_count: i32,
     pub quote_count: i32,
     pub total_points: i32,
+    pub root_uri: Option<String>,
+    pub refreshed_at: Option<NaiveDateTime>,
+    /// Refreshes in a row the post was missing from `getPosts`
+    pub missed_refreshes: i32,
+    pub archived_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -50,4 +58,211 @@ pub struct NewPost {
     pub like_count: i32,
     pub quote_count: i32,
     pub total_points: i32,
+    pub root_uri: Option<String>,
+}
+
+/// Audience of an account from its Bsky profile
+#[derive(AsChangeset)]
+#[diesel(table_name = super::schema::users)]
+pub struct UserAudience {
+    pub followers_count: Option<i32>,
+    pub posts_count: Option<i32>,
+}
+
+/// Fresh counters of a tracked post
+#[derive(AsChangeset)]
+#[diesel(table_name = super::schema::posts)]
+pub struct PostMetrics {
+    pub reply_count: i32,
+    pub repost_count: i32,
+    pub like_count: i32,
+    pub quote_count: i32,
+    pub total_points: i32,
+    pub refreshed_at: Option<NaiveDateTime>,
+    pub missed_refreshes: i32,
+}
+
+#[derive(Queryable, Selectable, Associations, Clone, Debug)]
+#[allow(dead_code)]
+#[diesel(table_name = super::schema::post_metrics_snapshots)]
+#[diesel(belongs_to(Post))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PostMetricsSnapshot {
+    pub id: i32,
+    pub created_at: NaiveDateTime,
+    pub post_id: i32,
+    pub reply_count: i32,
+    pub repost_count: i32,
+    pub like_count: i32,
+    pub quote_count: i32,
+    pub total_points: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::post_metrics_snapshots)]
+pub struct NewPostMetricsSnapshot {
+    pub post_id: i32,
+    pub reply_count: i32,
+    pub repost_count: i32,
+    pub like_count: i32,
+    pub quote_count: i32,
+    pub total_points: i32,
+}
+
+/// Counter of a post bumped by a live engagement
+#[derive(Clone, Copy)]
+pub enum PostCounter {
+    Like,
+    Repost,
+    Reply,
+    Quote,
+}
+
+#[derive(Queryable, Selectable, Clone, Debug)]
+#[allow(dead_code)]
+#[diesel(table_name = super::schema::publications)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Publication {
+    pub id: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub ranking_id: String,
+    pub position: i32,
+    pub user_id: Option<i32>,
+    pub text: String,
+    pub post_uri: Option<String>,
+    pub post_cid: Option<String>,
+    pub record_uri: Option<String>,
+    pub record_cid: Option<String>,
+    pub status: String,
+    /// Record key reserved for the entry before it is posted
+    pub record_rkey: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::publications)]
+pub struct NewPublication {
+    pub ranking_id: String,
+    pub position: i32,
+    pub user_id: Option<i32>,
+    pub text: String,
+    pub post_uri: Option<String>,
+    pub post_cid: Option<String>,
+    pub status: String,
+}
+
+pub enum PublicationStatus {
+    Pending,
+    /// Sent to `createRecord` without a recorded result, the record may exist
+    InFlight,
+    Published,
+}
+
+impl Post {
+    /// Uri of the thread the post belongs to, the post itself when it is not a reply.
+    pub fn thread_uri(&self) -> &str {
+        self.root_uri.as_deref().unwrap_or(&self.uri)
+    }
+}
+
+/// Archived ranking, kept after its posts leave the working set
+#[derive(Queryable, Selectable, Clone, Debug)]
+#[allow(dead_code)]
+#[diesel(table_name = super::schema::rankings)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Ranking {
+    pub id: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// Ranking id of the publication
+    pub key: String,
+    pub kind: String,
+    pub ranked_date: NaiveDate,
+    pub strategy: String,
+    /// Fingerprint of the scoring and ranking settings
+    pub config_hash: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::rankings)]
+pub struct NewRanking {
+    pub key: String,
+    pub kind: String,
+    pub ranked_date: NaiveDate,
+    pub strategy: String,
+    pub config_hash: String,
+}
+
+/// Ranked post with the counters it had when it was ranked
+#[derive(Queryable, Selectable, Associations, Clone, Debug)]
+#[allow(dead_code)]
+#[diesel(table_name = super::schema::ranking_entries)]
+#[diesel(belongs_to(Ranking))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct RankingEntry {
+    pub id: i32,
+    pub created_at: NaiveDateTime,
+    pub ranking_id: i32,
+    pub position: i32,
+    pub post_id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub post_uri: String,
+    pub post_created_at: NaiveDateTime,
+    pub reply_count: i32,
+    pub repost_count: i32,
+    pub like_count: i32,
+    pub quote_count: i32,
+    pub total_points: i32,
+    pub score: f64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = super::schema::ranking_entries)]
+pub struct NewRankingEntry {
+    pub ranking_id: i32,
+    pub position: i32,
+    pub post_id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub post_uri: String,
+    pub post_created_at: NaiveDateTime,
+    pub reply_count: i32,
+    pub repost_count: i32,
+    pub like_count: i32,
+    pub quote_count: i32,
+    pub total_points: i32,
+    pub score: f64,
+}
+
+/// Kind of a published ranking, used as the prefix of its ranking id
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RankingKind {
+    /// Top of the day-old posts
+    Daily,
+    /// Posts gaining engagement faster than usual right now
+    Trending,
+    /// Best of the stored posts of the last week
+    Weekly,
+    /// Best of the stored posts of the last month
+    Monthly,
+}
+
+impl RankingKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RankingKind::Daily => "daily",
+            RankingKind::Trending => "trending",
+            RankingKind::Weekly => "weekly",
+            RankingKind::Monthly => "monthly",
+        }
+    }
+}
+
+impl PublicationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PublicationStatus::Pending => "pending",
+            PublicationStatus::InFlight => "in_flight",
+            PublicationStatus::Published => "published",
+        }
+    }
 }
@@ -1,8 +1,9 @@
 use super::{models::*, schema};
 use crate::database::lib::{create_pool, establish_connection};
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::{prelude::*, result::Error};
 use futures::future::join_all;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use tokio::task;
 
 pub async fn sync_users(users_list: &Vec<NewUser>) -> Result<(), Error> {
@@ -33,6 +34,8 @@ pub async fn sync_users(users_list: &Vec<NewUser>) -> Result<(), Error> {
                     did: user.did.clone(),
                     handle: user.handle.clone(),
                     display_name: user.display_name.clone(),
+                    followers_count: existed_user.followers_count,
+                    posts_count: existed_user.posts_count,
                 });
             }
         } else {
@@ -62,13 +65,19 @@ pub async fn sync_users(users_list: &Vec<NewUser>) -> Result<(), Error> {
     Ok(())
 }
 
-pub fn get_total_users_count() -> Result<i64, Error> {
+pub fn update_users_audience(audiences: &[(String, UserAudience)]) -> Result<(), Error> {
     use super::schema::users::dsl::*;
     let mut conn = establish_connection();
 
-    let count = users.count().get_result(&mut conn)?;
+    conn.transaction(|conn| {
+        for (user_did, audience) in audiences {
+            diesel::update(users.filter(did.eq(user_did)))
+                .set(audience)
+                .execute(conn)?;
+        }
 
-    Ok(count)
+        Ok(())
+    })
 }
 
 pub fn get_users_list(limit: i64, cursor: i32) -> Result<Vec<User>, Error> {
@@ -84,39 +93,780 @@ pub fn get_users_list(limit: i64, cursor: i32) -> Result<Vec<User>, Error> {
     Ok(rows)
 }
 
+/// Inserts the posts, posts already stored under the same uri get the new
+/// counters instead.
 pub fn save_posts(new_posts: &Vec<NewPost>) -> Result<(), Error> {
+    use super::schema::posts::dsl::*;
+    // `filter` of an upsert is the `WHERE` of its `DO UPDATE`
+    use diesel::{query_dsl::methods::FilterDsl, upsert::excluded};
+    let mut conn = establish_connection();
+
+    // Diesel has no batch upsert for SQLite, a transaction keeps it fast
+    conn.transaction(|conn| {
+        for new_post in new_posts {
+            diesel::insert_into(posts)
+                .values(new_post)
+                .on_conflict(uri)
+                .do_update()
+                .set((
+                    cid.eq(excluded(cid)),
+                    reply_count.eq(excluded(reply_count)),
+                    repost_count.eq(excluded(repost_count)),
+                    like_count.eq(excluded(like_count)),
+                    quote_count.eq(excluded(quote_count)),
+                    total_points.eq(excluded(total_points)),
+                ))
+                // Archived posts keep the counters they were ranked with
+                .filter(archived_at.is_null())
+                .execute(conn)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// URIs of the posts whose counters are still kept up to date, archived
+/// posts are left out
+pub fn get_tracked_post_uris(post_uris: &[String]) -> Result<HashSet<String>, Error> {
+    use super::schema::posts::dsl::*;
+    let mut conn = establish_connection();
+
+    let mut tracked_uris: HashSet<String> = HashSet::new();
+    for chunk in post_uris.chunks(500) {
+        let rows = posts
+            .filter(uri.eq_any(chunk))
+            .filter(archived_at.is_null())
+            .select(uri)
+            .load::<String>(&mut conn)?;
+        tracked_uris.extend(rows);
+    }
+
+    Ok(tracked_uris)
+}
+
+/// URIs of the given list that are already stored
+pub fn get_known_post_uris(post_uris: &[String]) -> Result<HashSet<String>, Error> {
+    use super::schema::posts::dsl::*;
+    let mut conn = establish_connection();
+
+    let mut known_uris: HashSet<String> = HashSet::new();
+    // Stay far below the SQLite limit of bound parameters
+    for chunk in post_uris.chunks(500) {
+        let rows = posts
+            .filter(uri.eq_any(chunk))
+            .select(uri)
+            .load::<String>(&mut conn)?;
+        known_uris.extend(rows);
+    }
+
+    Ok(known_uris)
+}
+
+/// Posts created after `since` whose counters are still refreshed
+pub fn get_tracked_posts(since: NaiveDateTime) -> Result<Vec<Post>, Error> {
+    use super::schema::posts::dsl::*;
+    let mut conn = establish_connection();
+
+    let rows = posts
+        .filter(created_at.ge(since))
+        .filter(archived_at.is_null())
+        .order(id.asc())
+        .select(Post::as_select())
+        .load::<Post>(&mut conn)?;
+
+    Ok(rows)
+}
+
+pub fn update_post_metrics(metrics: &[(i32, PostMetrics)]) -> Result<(), Error> {
     use super::schema::posts::dsl::*;
     let mut conn = establish_connection();
 
-    if !new_posts.is_empty() {
-        diesel::insert_into(posts)
-            .values(new_posts)
+    conn.transaction(|conn| {
+        for (post_id, post_metrics) in metrics {
+            diesel::update(posts.find(post_id))
+                .set(post_metrics)
+                .execute(conn)?;
+        }
+
+        Ok(())
+    })
+}
+
+pub fn save_metrics_snapshots(snapshots: &[NewPostMetricsSnapshot]) -> Result<(), Error> {
+    use super::schema::post_metrics_snapshots::dsl::*;
+    let mut conn = establish_connection();
+
+    if !snapshots.is_empty() {
+        diesel::insert_into(post_metrics_snapshots)
+            .values(snapshots)
             .execute(&mut conn)?;
     }
 
     Ok(())
 }
 
-pub fn get_top_ten_posts_with_users() -> Result<Vec<(Post, User)>, Error> {
+/// Snapshots of the posts created after `since` with the posts and their
+/// authors, oldest snapshot first
+pub fn get_metrics_history(
+    since: NaiveDateTime,
+) -> Result<Vec<(PostMetricsSnapshot, Post, User)>, Error> {
+    let mut conn = establish_connection();
+
+    let rows = schema::post_metrics_snapshots::table
+        .inner_join(schema::posts::table.inner_join(schema::users::table))
+        .filter(schema::posts::created_at.ge(since))
+        .filter(schema::posts::archived_at.is_null())
+        .filter(schema::posts::missed_refreshes.eq(0))
+        .order(schema::post_metrics_snapshots::created_at.asc())
+        .select((
+            PostMetricsSnapshot::as_select(),
+            Post::as_select(),
+            User::as_select(),
+        ))
+        .load::<(PostMetricsSnapshot, Post, User)>(&mut conn)?;
+
+    Ok(rows)
+}
+
+/// Counts one more missed refresh for each post and returns the ones missed
+/// `max_missed` times in a row
+pub fn mark_posts_missing(post_ids: &[i32], max_missed: i32) -> Result<Vec<i32>, Error> {
+    use super::schema::posts::dsl::*;
+    let mut conn = establish_connection();
+
+    if post_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    conn.transaction(|conn| {
+        diesel::update(posts.filter(id.eq_any(post_ids)))
+            .set(missed_refreshes.eq(missed_refreshes + 1))
+            .execute(conn)?;
+
+        posts
+            .filter(id.eq_any(post_ids))
+            .filter(missed_refreshes.ge(max_missed))
+            .select(id)
+            .load::<i32>(conn)
+    })
+}
+
+pub fn delete_posts(post_ids: &[i32]) -> Result<(), Error> {
+    use super::schema::posts::dsl::*;
+    let mut conn = establish_connection();
+
+    if !post_ids.is_empty() {
+        conn.transaction(|conn| {
+            diesel::delete(
+                schema::post_metrics_snapshots::table
+                    .filter(schema::post_metrics_snapshots::post_id.eq_any(post_ids)),
+            )
+            .execute(conn)?;
+            unlink_ranking_entries(conn, post_ids)?;
+            diesel::delete(posts.filter(id.eq_any(post_ids))).execute(conn)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Archived entries keep the counters and uri of a post that is deleted
+fn unlink_ranking_entries(conn: &mut SqliteConnection, post_ids: &[i32]) -> Result<(), Error> {
+    use super::schema::ranking_entries::dsl::*;
+
+    diesel::update(ranking_entries.filter(post_id.eq_any(post_ids)))
+        .set(post_id.eq(None::<i32>))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Adds the engagements of one firehose event to the tracked posts and moves
+/// the stored cursor to the event in the same transaction, so a restart never
+/// counts them twice. Returns the number of engagements with tracked posts.
+pub fn count_engagements(
+    engagements: &[(&str, PostCounter, i32)],
+    cursor_name: &str,
+    cursor_value: i64,
+) -> Result<usize, Error> {
+    let mut conn = establish_connection();
+
+    conn.transaction(|conn| {
+        let mut counted = 0;
+        for (post_uri, counter, points) in engagements {
+            if increment_post_counter(conn, post_uri, *counter, *points)? {
+                counted += 1;
+            }
+        }
+        upsert_firehose_cursor(conn, cursor_name, cursor_value)?;
+
+        Ok(counted)
+    })
+}
+
+/// Adds one engagement to the post if it is tracked. Returns whether the post
+/// was found.
+fn increment_post_counter(
+    conn: &mut SqliteConnection,
+    post_uri: &str,
+    counter: PostCounter,
+    points: i32,
+) -> Result<bool, Error> {
+    use super::schema::posts::dsl::*;
+
+    let target = posts.filter(uri.eq(post_uri)).filter(archived_at.is_null());
+    let total = total_points.eq(total_points + points);
+
+    let updated = match counter {
+        PostCounter::Like => diesel::update(target)
+            .set((like_count.eq(like_count + 1), total))
+            .execute(conn)?,
+        PostCounter::Repost => diesel::update(target)
+            .set((repost_count.eq(repost_count + 1), total))
+            .execute(conn)?,
+        PostCounter::Reply => diesel::update(target)
+            .set((reply_count.eq(reply_count + 1), total))
+            .execute(conn)?,
+        PostCounter::Quote => diesel::update(target)
+            .set((quote_count.eq(quote_count + 1), total))
+            .execute(conn)?,
+    };
+
+    Ok(updated > 0)
+}
+
+pub fn get_firehose_cursor(cursor_name: &str) -> Result<Option<i64>, Error> {
+    use super::schema::firehose_cursors::dsl::*;
+    let mut conn = establish_connection();
+
+    let row = firehose_cursors
+        .filter(name.eq(cursor_name))
+        .select(cursor)
+        .first::<i64>(&mut conn)
+        .optional()?;
+
+    Ok(row)
+}
+
+pub fn save_firehose_cursor(cursor_name: &str, cursor_value: i64) -> Result<(), Error> {
+    let mut conn = establish_connection();
+
+    upsert_firehose_cursor(&mut conn, cursor_name, cursor_value)
+}
+
+fn upsert_firehose_cursor(
+    conn: &mut SqliteConnection,
+    cursor_name: &str,
+    cursor_value: i64,
+) -> Result<(), Error> {
+    use super::schema::firehose_cursors::dsl::*;
+
+    diesel::insert_into(firehose_cursors)
+        .values((name.eq(cursor_name), cursor.eq(cursor_value)))
+        .on_conflict(name)
+        .do_update()
+        .set(cursor.eq(cursor_value))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Posts created before `before`, scores are computed by the ranking. Every
+/// post comes once, the uri is unique and each post has a single author.
+pub fn get_ranking_candidates(before: NaiveDateTime) -> Result<Vec<(Post, User)>, Error> {
+    let mut conn = establish_connection();
+
+    let rows: Vec<(Post, User)> = schema::posts::table
+        .inner_join(schema::users::table)
+        .filter(schema::posts::created_at.le(before))
+        .filter(schema::posts::archived_at.is_null())
+        .filter(schema::posts::missed_refreshes.eq(0))
+        .order(schema::posts::id.asc())
+        .select((Post::as_select(), User::as_select()))
+        .load::<(Post, User)>(&mut conn)?;
+
+    Ok(rows)
+}
+
+/// Posts created between `since` and `before`, archived ones included
+pub fn get_period_candidates(
+    since: NaiveDateTime,
+    before: NaiveDateTime,
+) -> Result<Vec<(Post, User)>, Error> {
     let mut conn = establish_connection();
 
     let rows: Vec<(Post, User)> = schema::posts::table
         .inner_join(schema::users::table)
-        .filter(schema::posts::total_points.gt(0))
-        .order(schema::posts::total_points.desc())
-        .limit(10)
+        .filter(schema::posts::created_at.gt(since))
+        .filter(schema::posts::created_at.le(before))
+        .order(schema::posts::id.asc())
         .select((Post::as_select(), User::as_select()))
         .load::<(Post, User)>(&mut conn)?;
 
     Ok(rows)
 }
 
-pub fn drop_all_posts() -> Result<(), Error> {
+/// Moves posts created before `before` out of the working set, younger ones
+/// are still tracked. Their snapshots are only needed while they are young.
+pub fn archive_posts_before(before: NaiveDateTime) -> Result<(), Error> {
+    use super::schema::posts::dsl::*;
+    let mut conn = establish_connection();
+
+    conn.transaction(|conn| {
+        let archived_posts = posts.filter(created_at.le(before)).select(id);
+        diesel::delete(
+            schema::post_metrics_snapshots::table
+                .filter(schema::post_metrics_snapshots::post_id.eq_any(archived_posts)),
+        )
+        .execute(conn)?;
+
+        diesel::update(
+            posts
+                .filter(created_at.le(before))
+                .filter(archived_at.is_null()),
+        )
+        .set(archived_at.eq(diesel::dsl::now))
+        .execute(conn)
+    })?;
+
+    Ok(())
+}
+
+/// Deletes posts archived before `before`. Returns the number of deleted posts.
+pub fn delete_archived_posts_before(before: NaiveDateTime) -> Result<usize, Error> {
     use super::schema::posts::dsl::*;
+    let mut conn = establish_connection();
 
+    conn.transaction(|conn| {
+        let post_ids: Vec<i32> = posts.filter(archived_at.le(before)).select(id).load(conn)?;
+
+        for chunk in post_ids.chunks(500) {
+            unlink_ranking_entries(conn, chunk)?;
+            diesel::delete(posts.filter(id.eq_any(chunk))).execute(conn)?;
+        }
+
+        Ok(post_ids.len())
+    })
+}
+
+/// Deletes rankings of days before `before` with their entries. Returns the
+/// number of deleted rankings.
+pub fn delete_rankings_before(before: NaiveDate) -> Result<usize, Error> {
+    use super::schema::rankings::dsl::*;
     let mut conn = establish_connection();
 
-    diesel::delete(posts).execute(&mut conn)?;
+    conn.transaction(|conn| {
+        let old_rankings = rankings.filter(ranked_date.lt(before)).select(id);
+        diesel::delete(
+            schema::ranking_entries::table
+                .filter(schema::ranking_entries::ranking_id.eq_any(old_rankings)),
+        )
+        .execute(conn)?;
+
+        diesel::delete(rankings.filter(ranked_date.lt(before))).execute(conn)
+    })
+}
+
+/// Stores the publication log of a ranking together with its archived copy,
+/// so a ranking is never published without being archived.
+pub fn create_archived_publication(
+    entries: &Vec<NewPublication>,
+    new_ranking: &NewRanking,
+    ranking_entries: Vec<NewRankingEntry>,
+) -> Result<(), Error> {
+    let mut conn = establish_connection();
+
+    conn.transaction(|conn| {
+        diesel::insert_into(schema::publications::table)
+            .values(entries)
+            .execute(conn)?;
+
+        insert_ranking(conn, new_ranking, ranking_entries)
+    })
+}
+
+fn insert_ranking(
+    conn: &mut SqliteConnection,
+    new_ranking: &NewRanking,
+    mut entries: Vec<NewRankingEntry>,
+) -> Result<(), Error> {
+    diesel::insert_into(schema::rankings::table)
+        .values(new_ranking)
+        .execute(conn)?;
+
+    let new_ranking_id = schema::rankings::table
+        .filter(schema::rankings::key.eq(&new_ranking.key))
+        .select(schema::rankings::id)
+        .first::<i32>(conn)?;
+
+    for entry in entries.iter_mut() {
+        entry.ranking_id = new_ranking_id;
+    }
+
+    diesel::insert_into(schema::ranking_entries::table)
+        .values(&entries)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Latest archived rankings, newest first
+pub fn get_rankings(limit: i64) -> Result<Vec<Ranking>, Error> {
+    use super::schema::rankings::dsl::*;
+    let mut conn = establish_connection();
+
+    let rows = rankings
+        .order(id.desc())
+        .limit(limit)
+        .select(Ranking::as_select())
+        .load::<Ranking>(&mut conn)?;
+
+    Ok(rows)
+}
+
+/// Archived ranking with its entries in rank order, the user is missing when
+/// its row was deleted since
+pub type ArchivedRanking = (Ranking, Vec<(RankingEntry, Option<User>)>);
+
+pub fn get_ranking(ranking_key: &str) -> Result<Option<ArchivedRanking>, Error> {
+    let mut conn = establish_connection();
+
+    let ranking = schema::rankings::table
+        .filter(schema::rankings::key.eq(ranking_key))
+        .select(Ranking::as_select())
+        .first::<Ranking>(&mut conn)
+        .optional()?;
+
+    let Some(ranking) = ranking else {
+        return Ok(None);
+    };
+
+    let entries = schema::ranking_entries::table
+        .left_join(schema::users::table)
+        .filter(schema::ranking_entries::ranking_id.eq(ranking.id))
+        .order(schema::ranking_entries::position.asc())
+        .select((RankingEntry::as_select(), Option::<User>::as_select()))
+        .load::<(RankingEntry, Option<User>)>(&mut conn)?;
+
+    Ok(Some((ranking, entries)))
+}
+
+/// Post uris of the latest archived ranking of the kind for the day, in rank
+/// order. `None` when no such ranking was archived
+pub fn get_archived_ranking_uris(
+    ranking_kind: &str,
+    day: NaiveDate,
+) -> Result<Option<Vec<String>>, Error> {
+    let mut conn = establish_connection();
+
+    let ranking_id = schema::rankings::table
+        .filter(schema::rankings::kind.eq(ranking_kind))
+        .filter(schema::rankings::ranked_date.eq(day))
+        .order(schema::rankings::id.desc())
+        .select(schema::rankings::id)
+        .first::<i32>(&mut conn)
+        .optional()?;
+
+    let Some(ranking_id) = ranking_id else {
+        return Ok(None);
+    };
+
+    let uris = schema::ranking_entries::table
+        .filter(schema::ranking_entries::ranking_id.eq(ranking_id))
+        .order(schema::ranking_entries::position.asc())
+        .select(schema::ranking_entries::post_uri)
+        .load::<String>(&mut conn)?;
+
+    Ok(Some(uris))
+}
+
+/// Every archived ranking entry of the user, newest first
+pub fn get_user_appearances(user_id: i32) -> Result<Vec<(RankingEntry, Ranking)>, Error> {
+    let mut conn = establish_connection();
+
+    let rows = schema::ranking_entries::table
+        .inner_join(schema::rankings::table)
+        .filter(schema::ranking_entries::user_id.eq(user_id))
+        .order(schema::rankings::ranked_date.desc())
+        .select((RankingEntry::as_select(), Ranking::as_select()))
+        .load::<(RankingEntry, Ranking)>(&mut conn)?;
+
+    Ok(rows)
+}
+
+/// Daily top appearances of an author
+pub struct AuthorStats {
+    pub user: User,
+    /// Entries in the daily rankings of the period
+    pub appearances: i64,
+    pub best_position: i32,
+    /// Consecutive days ranked up to the latest daily ranking, 0 when the
+    /// author is not in it
+    pub current_streak: i64,
+    /// Points of the ranked posts of the period
+    pub total_points: i64,
+}
+
+/// Authors of the daily rankings since `since`, most often ranked first. Ties
+/// go to more points, then to the better position.
+pub fn get_author_leaderboard(since: NaiveDate) -> Result<Vec<AuthorStats>, Error> {
+    let mut conn = establish_connection();
+
+    let rows = schema::ranking_entries::table
+        .inner_join(schema::rankings::table)
+        .inner_join(schema::users::table)
+        .filter(schema::rankings::kind.eq(RankingKind::Daily.as_str()))
+        .select((
+            schema::ranking_entries::position,
+            schema::ranking_entries::total_points,
+            schema::rankings::ranked_date,
+            User::as_select(),
+        ))
+        .load::<(i32, i32, NaiveDate, User)>(&mut conn)?;
+
+    let Some(latest_date) = rows.iter().map(|(_, _, date, _)| *date).max() else {
+        return Ok(vec![]);
+    };
+
+    let mut ranked_dates: HashMap<i32, HashSet<NaiveDate>> = HashMap::new();
+    let mut authors: HashMap<i32, AuthorStats> = HashMap::new();
+    for (position, points, date, user) in rows {
+        ranked_dates.entry(user.id).or_default().insert(date);
+
+        if date < since {
+            continue;
+        }
+
+        let stats = authors.entry(user.id).or_insert_with(|| AuthorStats {
+            user,
+            appearances: 0,
+            best_position: position,
+            current_streak: 0,
+            total_points: 0,
+        });
+        stats.appearances += 1;
+        stats.best_position = stats.best_position.min(position);
+        stats.total_points += points as i64;
+    }
+
+    let mut leaderboard: Vec<AuthorStats> = authors
+        .into_values()
+        .map(|mut stats| {
+            let dates = &ranked_dates[&stats.user.id];
+            let mut date = latest_date;
+            while dates.contains(&date) {
+                stats.current_streak += 1;
+                date -= chrono::Duration::days(1);
+            }
+            stats
+        })
+        .collect();
+
+    leaderboard.sort_by(|a, b| {
+        b.appearances
+            .cmp(&a.appearances)
+            .then(b.total_points.cmp(&a.total_points))
+            .then(a.best_position.cmp(&b.best_position))
+    });
+
+    Ok(leaderboard)
+}
+
+pub fn get_user_by_handle(user_handle: &str) -> Result<Option<User>, Error> {
+    use super::schema::users::dsl::*;
+    let mut conn = establish_connection();
+
+    let row = users
+        .filter(handle.eq(user_handle))
+        .select(User::as_select())
+        .first::<User>(&mut conn)
+        .optional()?;
+
+    Ok(row)
+}
+
+/// Ranking id of the latest publication of the kind with pending or in-flight
+/// entries
+pub fn get_unfinished_ranking_id(kind: &str) -> Result<Option<String>, Error> {
+    use super::schema::publications::dsl::*;
+    let mut conn = establish_connection();
+
+    let row = publications
+        .filter(status.ne(PublicationStatus::Published.as_str()))
+        .filter(ranking_id.like(format!("{}-%", kind)))
+        .order(id.desc())
+        .select(ranking_id)
+        .first::<String>(&mut conn)
+        .optional()?;
+
+    Ok(row)
+}
+
+pub fn create_publication(entries: &Vec<NewPublication>) -> Result<(), Error> {
+    use super::schema::publications::dsl::*;
+    let mut conn = establish_connection();
+
+    conn.transaction(|conn| {
+        diesel::insert_into(publications)
+            .values(entries)
+            .execute(conn)
+    })?;
 
     Ok(())
 }
+
+/// URIs of the posts that were already part of a ranking of the kind
+pub fn get_published_post_uris(kind: RankingKind) -> Result<HashSet<String>, Error> {
+    use super::schema::publications::dsl::*;
+    let mut conn = establish_connection();
+
+    let rows = publications
+        .filter(ranking_id.like(format!("{}-%", kind.as_str())))
+        .filter(post_uri.is_not_null())
+        .select(post_uri.assume_not_null())
+        .load::<String>(&mut conn)?;
+
+    Ok(rows.into_iter().collect())
+}
+
+pub fn get_publication_entries(
+    publication_ranking_id: &str,
+) -> Result<Vec<(Publication, Option<User>)>, Error> {
+    let mut conn = establish_connection();
+
+    let rows = schema::publications::table
+        .left_join(schema::users::table)
+        .filter(schema::publications::ranking_id.eq(publication_ranking_id))
+        .order(schema::publications::position.asc())
+        .select((Publication::as_select(), Option::<User>::as_select()))
+        .load::<(Publication, Option<User>)>(&mut conn)?;
+
+    Ok(rows)
+}
+
+/// Claims the entry right before it is posted, so concurrent runs can't post
+/// it twice. A pending entry, or an in-flight one not touched since
+/// `stale_before`, is set in flight with its record key. Returns the record
+/// key to post with, the one reserved by an earlier run is kept, or `None`
+/// when another run holds the entry.
+pub fn claim_publication(
+    publication_id: i32,
+    rkey: &str,
+    stale_before: NaiveDateTime,
+) -> Result<Option<String>, Error> {
+    use super::schema::publications::dsl::*;
+    let mut conn = establish_connection();
+
+    conn.transaction(|conn| {
+        let claimed = diesel::update(
+            publications.find(publication_id).filter(
+                status.eq(PublicationStatus::Pending.as_str()).or(status
+                    .eq(PublicationStatus::InFlight.as_str())
+                    .and(updated_at.lt(stale_before))),
+            ),
+        )
+        .set(status.eq(PublicationStatus::InFlight.as_str()))
+        .execute(conn)?;
+
+        if claimed == 0 {
+            return Ok(None);
+        }
+
+        let reserved: Option<String> = publications
+            .find(publication_id)
+            .select(record_rkey)
+            .first(conn)?;
+        if reserved.is_some() {
+            return Ok(reserved);
+        }
+
+        diesel::update(publications.find(publication_id))
+            .set(record_rkey.eq(rkey))
+            .execute(conn)?;
+
+        Ok(Some(rkey.to_string()))
+    })
+}
+
+/// Hands an entry claimed by `claim_publication` back to the next run, its
+/// record key stays reserved
+pub fn release_publication(publication_id: i32) -> Result<(), Error> {
+    use super::schema::publications::dsl::*;
+    let mut conn = establish_connection();
+
+    diesel::update(
+        publications
+            .find(publication_id)
+            .filter(status.eq(PublicationStatus::InFlight.as_str())),
+    )
+    .set(status.eq(PublicationStatus::Pending.as_str()))
+    .execute(&mut conn)?;
+
+    Ok(())
+}
+
+pub fn mark_publication_published(
+    publication_id: i32,
+    published_uri: &str,
+    published_cid: &str,
+) -> Result<(), Error> {
+    use super::schema::publications::dsl::*;
+    let mut conn = establish_connection();
+
+    diesel::update(publications.find(publication_id))
+        .set((
+            record_uri.eq(published_uri),
+            record_cid.eq(published_cid),
+            status.eq(PublicationStatus::Published.as_str()),
+        ))
+        .execute(&mut conn)?;
+
+    Ok(())
+}
+
+pub struct DatabaseStats {
+    pub users: i64,
+    pub posts: i64,
+    pub archived_posts: i64,
+    pub rankings: i64,
+    pub publications: i64,
+    pub pending_publications: i64,
+    pub latest_publication: Option<Publication>,
+}
+
+pub fn get_stats() -> Result<DatabaseStats, Error> {
+    let mut conn = establish_connection();
+
+    let users = schema::users::table.count().get_result(&mut conn)?;
+    let posts = schema::posts::table
+        .filter(schema::posts::archived_at.is_null())
+        .count()
+        .get_result(&mut conn)?;
+    let archived_posts = schema::posts::table
+        .filter(schema::posts::archived_at.is_not_null())
+        .count()
+        .get_result(&mut conn)?;
+    let rankings = schema::rankings::table.count().get_result(&mut conn)?;
+    let publications = schema::publications::table
+        .select(diesel::dsl::count(schema::publications::ranking_id).aggregate_distinct())
+        .get_result(&mut conn)?;
+    let pending_publications = schema::publications::table
+        .filter(schema::publications::status.ne(PublicationStatus::Published.as_str()))
+        .select(diesel::dsl::count(schema::publications::ranking_id).aggregate_distinct())
+        .get_result(&mut conn)?;
+    let latest_publication = schema::publications::table
+        .order(schema::publications::id.desc())
+        .select(Publication::as_select())
+        .first::<Publication>(&mut conn)
+        .optional()?;
+
+    Ok(DatabaseStats {
+        users,
+        posts,
+        archived_posts,
+        rankings,
+        publications,
+        pending_publications,
+        latest_publication,
+    })
+}
@@ -1,5 +1,28 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    firehose_cursors (id) {
+        id -> Integer,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        name -> Text,
+        cursor -> BigInt,
+    }
+}
+
+diesel::table! {
+    post_metrics_snapshots (id) {
+        id -> Integer,
+        created_at -> Timestamp,
+        post_id -> Integer,
+        reply_count -> Integer,
+        repost_count -> Integer,
+        like_count -> Integer,
+        quote_count -> Integer,
+        total_points -> Integer,
+    }
+}
+
 diesel::table! {
     posts (id) {
         id -> Integer,
@@ -12,6 +35,60 @@ diesel::table! {
         like_count -> Integer,
         quote_count -> Integer,
         total_points -> Integer,
+        root_uri -> Nullable<Text>,
+        refreshed_at -> Nullable<Timestamp>,
+        missed_refreshes -> Integer,
+        archived_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    publications (id) {
+        id -> Integer,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        ranking_id -> Text,
+        position -> Integer,
+        user_id -> Nullable<Integer>,
+        text -> Text,
+        post_uri -> Nullable<Text>,
+        post_cid -> Nullable<Text>,
+        record_uri -> Nullable<Text>,
+        record_cid -> Nullable<Text>,
+        status -> Text,
+        record_rkey -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    ranking_entries (id) {
+        id -> Integer,
+        created_at -> Timestamp,
+        ranking_id -> Integer,
+        position -> Integer,
+        post_id -> Nullable<Integer>,
+        user_id -> Nullable<Integer>,
+        post_uri -> Text,
+        post_created_at -> Timestamp,
+        reply_count -> Integer,
+        repost_count -> Integer,
+        like_count -> Integer,
+        quote_count -> Integer,
+        total_points -> Integer,
+        score -> Double,
+    }
+}
+
+diesel::table! {
+    rankings (id) {
+        id -> Integer,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        key -> Text,
+        kind -> Text,
+        ranked_date -> Date,
+        strategy -> Text,
+        config_hash -> Text,
     }
 }
 
@@ -23,12 +100,24 @@ diesel::table! {
         did -> Text,
         handle -> Text,
         display_name -> Nullable<Text>,
+        followers_count -> Nullable<Integer>,
+        posts_count -> Nullable<Integer>,
     }
 }
 
+diesel::joinable!(post_metrics_snapshots -> posts (post_id));
 diesel::joinable!(posts -> users (user_id));
+diesel::joinable!(publications -> users (user_id));
+diesel::joinable!(ranking_entries -> posts (post_id));
+diesel::joinable!(ranking_entries -> rankings (ranking_id));
+diesel::joinable!(ranking_entries -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    firehose_cursors,
+    post_metrics_snapshots,
     posts,
+    publications,
+    ranking_entries,
+    rankings,
     users,
 );
@@ -1,70 +1,147 @@
+use cli::{ArchiveCommand, Cli, Command, DbCommand};
+use config::Config;
+use database::models::RankingKind;
 use dotenv::dotenv;
-use services::bsky::Bsky;
-use std::time::Duration;
-use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
+use services::bsky::{recording::Replayer, Bsky};
+use std::{env, path::PathBuf, process, sync::Arc};
+use tokio_cron_scheduler::JobSchedulerError;
 
+mod cli;
+mod commands;
+mod config;
 mod database;
+mod ranking;
+mod scheduler;
 mod services;
 mod tasks;
+#[cfg(test)]
+mod tests;
 mod utils;
 
 #[tokio::main]
 async fn main() -> Result<(), JobSchedulerError> {
-    println!("App is running");
+    let cli = Cli::parse_args();
 
     dotenv().ok();
 
-    database::embeded_migrations::migrate();
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            println!("{}", error);
+            process::exit(1);
+        }
+    };
 
-    let bsky = Bsky::new();
-    tasks::sync_users(&bsky).await;
-    drop(bsky);
-
-    let mut sched = JobScheduler::new().await?;
+    if let Some(Command::SyncPosts {
+        window: Some((start, end)),
+    }) = cli.command
+    {
+        config.collection.window_start_hours = start;
+        config.collection.window_end_hours = end;
+    }
 
-    sched
-        .add(Job::new_async("0 0 12 */1 * *", |uuid, mut l| {
-            Box::pin(async move {
-                let mut bsky = Bsky::new();
+    if cli.dry_run {
+        config.publish.dry_run = true;
+    }
 
-                if !bsky.is_authenticated() {
-                    let auth_res = bsky.authenticate().await;
-                    if auth_res.is_err() {
-                        println!(
-                            "Error during authentication to bsky: {}",
-                            auth_res.unwrap_err()
-                        );
-                    }
-                }
+    let config = Arc::new(config);
 
-                tasks::sync_users(&bsky).await;
-                tasks::post_top_ten(&bsky).await;
-                l.next_tick_for_job(uuid).await.unwrap();
-            })
-        })?)
-        .await?;
+    let replayer = match &cli.replay_fixtures {
+        Some(dir) => match Replayer::load(dir) {
+            Ok(replayer) => Some(replayer),
+            Err(error) => {
+                println!("Unable to load fixtures from {}: {}", dir.display(), error);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
 
-    sched
-        .add(Job::new_async("0 0 */1 * * *", move |uuid, mut l| {
-            Box::pin(async move {
-                let bsky = Bsky::new();
-                tasks::sync_users_posts(&bsky).await;
-                l.next_tick_for_job(uuid).await.unwrap();
-            })
-        })?)
-        .await?;
+    // A replay runs in its own database as of the time of the recording, so
+    // it never mixes with the collected posts
+    if let (Some(dir), Some(replayer)) = (&cli.replay_fixtures, &replayer) {
+        let database = cli
+            .replay_database
+            .clone()
+            .unwrap_or_else(|| dir.join("replay.db"));
+        println!("Replaying into {}", database.display());
+        env::set_var("DATABASE_URL", &database);
+        utils::clock::pin(replayer.recorded_at());
+    }
 
-    sched.shutdown_on_ctrl_c();
+    // Only the daemon and `migrate` change the schema, other commands refuse
+    // to run against an outdated database. A replay database is a scratch one
+    if replayer.is_some() || matches!(cli.command, None | Some(Command::Run | Command::Migrate)) {
+        database::embeded_migrations::migrate();
+    } else if database::embeded_migrations::has_pending() {
+        println!("Database has pending migrations, apply them with `bsky-top10-bot migrate`");
+        process::exit(1);
+    }
 
-    sched.set_shutdown_handler(Box::new(|| {
-        Box::pin(async move {
-            println!("App was closed");
-        })
-    }));
+    // Single client for the whole process so every job shares one session
+    let mut bsky = Bsky::builder()
+        .entryway(&config.bsky.entryway)
+        .pds(config.bsky.pds.as_deref())
+        .appview(&config.bsky.appview)
+        .lang(&config.publish.lang)
+        .max_requests_per_second(config.collection.max_requests_per_second);
+    if config.publish.dry_run {
+        bsky = bsky.dry_run(config.publish.dry_run_output.as_ref().map(PathBuf::from));
+    }
+    if let Some(dir) = cli.record_fixtures {
+        bsky = bsky.record(dir);
+    }
+    if let Some(replayer) = replayer {
+        bsky = bsky.replay(replayer);
+    }
+    let bsky = bsky.build();
 
-    sched.start().await?;
+    let res = match cli.command.unwrap_or(Command::Run) {
+        Command::Run => return scheduler::run(bsky, config).await,
+        Command::SyncUsers => tasks::sync_users(&bsky, &config).await,
+        Command::SyncPosts { .. } => tasks::collect_posts(&bsky, &config).await,
+        Command::RefreshPosts => tasks::refresh_posts_metrics(&bsky, &config).await,
+        Command::Firehose { replay: None, .. } => tasks::run_firehose(&config).await,
+        Command::Firehose {
+            replay: Some(path),
+            apply: true,
+        } => tasks::apply_firehose(&config, &path),
+        Command::Firehose {
+            replay: Some(path),
+            apply: false,
+        } => tasks::replay_firehose(&config, &path),
+        Command::Rank { preview, kind } => commands::rank(&config, kind.kind(), preview),
+        Command::Publish { authors: true, .. } => {
+            tasks::post_authors_leaderboard(&bsky, &config).await
+        }
+        Command::Publish { kind, .. } => match kind.kind() {
+            RankingKind::Daily => tasks::post_top_ten(&bsky, &config).await,
+            RankingKind::Trending => tasks::post_trending(&bsky, &config).await,
+            kind => tasks::post_best_of(&bsky, &config, kind).await,
+        },
+        Command::Backtest { days, variants, .. } => commands::backtest(&config, days, &variants),
+        Command::Migrate => {
+            println!("Database migrations are applied");
+            Ok(())
+        }
+        Command::Archive { command } => match command {
+            ArchiveCommand::List { limit } => commands::archive_list(limit),
+            ArchiveCommand::Show { key } => commands::archive_show(&key),
+            ArchiveCommand::Appearances { handle } => commands::archive_appearances(&handle),
+            ArchiveCommand::Leaderboard { days, limit } => {
+                let days = days.unwrap_or(config.leaderboard.days);
+                commands::archive_leaderboard(days, limit)
+            }
+        },
+        Command::Db {
+            command: DbCommand::Stats,
+        } => commands::db_stats(),
+    };
 
-    loop {
-        tokio::time::sleep(Duration::from_secs(31536000)).await;
+    if let Err(error) = res {
+        println!("{}", error);
+        process::exit(1);
     }
+
+    Ok(())
 }
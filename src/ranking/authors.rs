@@ -0,0 +1,23 @@
+use super::{sort_by_score, Candidate};
+use std::collections::HashMap;
+
+/// Turns scored posts into a ranking of their authors. The score of an author
+/// is the sum of the scores of their posts, their best post represents them
+/// in the thread. Candidates are expected best first.
+pub fn rank_authors(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mut authors: HashMap<i32, Candidate> = HashMap::new();
+
+    for candidate in candidates {
+        match authors.get_mut(&candidate.user.id) {
+            Some(author) => author.score += candidate.score,
+            None => {
+                authors.insert(candidate.user.id, candidate);
+            }
+        }
+    }
+
+    let mut authors: Vec<Candidate> = authors.into_values().collect();
+    sort_by_score(&mut authors);
+
+    authors
+}
@@ -0,0 +1,214 @@
+use super::{diversity::select_diverse, score_candidates_at, scoring, Candidate};
+use crate::{
+    config::Config,
+    database::models::{Post, User},
+};
+use chrono::Duration;
+use std::collections::{HashMap, HashSet};
+
+/// Ranking of a past day under one configuration
+pub struct DayRanking {
+    /// Uris of every post with a positive score, best first
+    pub order: Vec<String>,
+    /// Entries the daily ranking would have published
+    pub top: Vec<Candidate>,
+}
+
+/// Ranks the posts of a past day like the daily ranking does, every post
+/// scored at the age the daily ranking sees it at.
+pub fn rank_day(config: &Config, posts_with_users: Vec<(Post, User)>) -> DayRanking {
+    let ranked_age = Duration::hours(config.collection.window_start_hours);
+    let mut strategy = scoring::strategy_from_config(&config.scoring);
+    let candidates = score_candidates_at(strategy.as_mut(), posts_with_users, |post| {
+        post.created_at + ranked_age
+    });
+
+    let order = candidates
+        .iter()
+        .map(|candidate| candidate.post.uri.clone())
+        .collect();
+    let top = select_diverse(
+        candidates,
+        config.ranking.size,
+        config.ranking.max_posts_per_author,
+    );
+
+    DayRanking { order, top }
+}
+
+/// Agreement of two rankings of the same day
+pub struct Comparison {
+    /// Number of posts in both tops
+    pub overlap: usize,
+    /// Spearman's rho of the full orders, over the posts both scored
+    pub spearman: Option<f64>,
+    /// Kendall's tau of the full orders, over the posts both scored
+    pub kendall: Option<f64>,
+}
+
+pub fn compare(base: &DayRanking, other: &DayRanking) -> Comparison {
+    let base_top: HashSet<&str> = base
+        .top
+        .iter()
+        .map(|candidate| candidate.post.uri.as_str())
+        .collect();
+    let overlap = other
+        .top
+        .iter()
+        .filter(|candidate| base_top.contains(candidate.post.uri.as_str()))
+        .count();
+
+    let ranks = common_ranks(&base.order, &other.order);
+
+    Comparison {
+        overlap,
+        spearman: spearman(&ranks),
+        kendall: kendall(&ranks),
+    }
+}
+
+/// Agreement of the ranking with the top archived when the day was published.
+/// Rho and tau only cover the archived posts the ranking scored.
+pub fn compare_published(ranking: &DayRanking, published: &[String]) -> Comparison {
+    let overlap = ranking
+        .top
+        .iter()
+        .filter(|candidate| published.contains(&candidate.post.uri))
+        .count();
+
+    let ranks = common_ranks(published, &ranking.order);
+
+    Comparison {
+        overlap,
+        spearman: spearman(&ranks),
+        kendall: kendall(&ranks),
+    }
+}
+
+/// Ranks in `b` of the items both orders contain, listed in the order of `a`.
+/// Both sides are ranked among the common items only.
+fn common_ranks(a: &[String], b: &[String]) -> Vec<usize> {
+    let positions: HashMap<&str, usize> = b
+        .iter()
+        .enumerate()
+        .map(|(position, item)| (item.as_str(), position))
+        .collect();
+
+    let b_positions: Vec<usize> = a
+        .iter()
+        .filter_map(|item| positions.get(item.as_str()).copied())
+        .collect();
+
+    let mut by_position: Vec<usize> = (0..b_positions.len()).collect();
+    by_position.sort_by_key(|&index| b_positions[index]);
+
+    let mut ranks = vec![0; b_positions.len()];
+    for (rank, index) in by_position.into_iter().enumerate() {
+        ranks[index] = rank;
+    }
+
+    ranks
+}
+
+/// Orders have no ties, so `1 - 6Σd² / (n(n² - 1))` applies
+fn spearman(ranks: &[usize]) -> Option<f64> {
+    let n = ranks.len() as f64;
+    if ranks.len() < 2 {
+        return None;
+    }
+
+    let squared_differences: f64 = ranks
+        .iter()
+        .enumerate()
+        .map(|(rank, other)| (rank as f64 - *other as f64).powi(2))
+        .sum();
+
+    Some(1.0 - 6.0 * squared_differences / (n * (n * n - 1.0)))
+}
+
+/// Concordant minus discordant pairs over all pairs
+fn kendall(ranks: &[usize]) -> Option<f64> {
+    let n = ranks.len();
+    if n < 2 {
+        return None;
+    }
+
+    let mut balance: i64 = 0;
+    for i in 0..n {
+        for j in (i + 1)..n {
+            balance += if ranks[i] < ranks[j] { 1 } else { -1 };
+        }
+    }
+
+    Some(balance as f64 / (n * (n - 1) / 2) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        let value = value.expect("statistic is defined");
+        assert!(
+            (value - expected).abs() < 1e-9,
+            "{} is not {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn identical_orders_agree_fully() {
+        let ranks = common_ranks(&order(&["a", "b", "c", "d"]), &order(&["a", "b", "c", "d"]));
+
+        assert_eq!(ranks, vec![0, 1, 2, 3]);
+        assert_close(spearman(&ranks), 1.0);
+        assert_close(kendall(&ranks), 1.0);
+    }
+
+    #[test]
+    fn reversed_orders_disagree_fully() {
+        let ranks = common_ranks(&order(&["a", "b", "c", "d"]), &order(&["d", "c", "b", "a"]));
+
+        assert_eq!(ranks, vec![3, 2, 1, 0]);
+        assert_close(spearman(&ranks), -1.0);
+        assert_close(kendall(&ranks), -1.0);
+    }
+
+    #[test]
+    fn one_swap() {
+        let ranks = common_ranks(&order(&["a", "b", "c", "d"]), &order(&["a", "c", "b", "d"]));
+
+        // Σd² = 2, one discordant pair out of six
+        assert_eq!(ranks, vec![0, 2, 1, 3]);
+        assert_close(spearman(&ranks), 0.8);
+        assert_close(kendall(&ranks), 4.0 / 6.0);
+    }
+
+    #[test]
+    fn partial_overlap_ranks_common_items_only() {
+        let ranks = common_ranks(
+            &order(&["a", "b", "c", "d"]),
+            &order(&["x", "c", "a", "y", "b"]),
+        );
+
+        // `d` and the posts only `b` has are left out, c < a < b remains
+        assert_eq!(ranks, vec![1, 2, 0]);
+        assert_close(spearman(&ranks), -0.5);
+        assert_close(kendall(&ranks), -1.0 / 3.0);
+    }
+
+    #[test]
+    fn statistics_need_two_common_items() {
+        let ranks = common_ranks(&order(&["a", "b"]), &order(&["b", "c"]));
+
+        assert_eq!(ranks, vec![0]);
+        assert_eq!(spearman(&ranks), None);
+        assert_eq!(kendall(&ranks), None);
+        assert_eq!(spearman(&[]), None);
+    }
+}
@@ -0,0 +1,34 @@
+use super::Candidate;
+use std::collections::{HashMap, HashSet};
+
+/// Picks the ranking entries from candidates sorted by score, keeping at most
+/// `max_posts_per_author` posts of the same user and a single post per thread.
+pub fn select_diverse(
+    candidates: Vec<Candidate>,
+    limit: usize,
+    max_posts_per_author: usize,
+) -> Vec<Candidate> {
+    let mut posts_per_author: HashMap<i32, usize> = HashMap::new();
+    let mut threads: HashSet<String> = HashSet::new();
+    let mut selected: Vec<Candidate> = Vec::new();
+
+    for candidate in candidates {
+        if selected.len() >= limit {
+            break;
+        }
+
+        let author_posts = posts_per_author.entry(candidate.user.id).or_insert(0);
+        if *author_posts >= max_posts_per_author {
+            continue;
+        }
+
+        if !threads.insert(candidate.post.thread_uri().to_string()) {
+            continue;
+        }
+
+        *author_posts += 1;
+        selected.push(candidate);
+    }
+
+    selected
+}
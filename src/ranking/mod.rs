@@ -0,0 +1,61 @@
+use crate::database::models::{Post, User};
+use chrono::NaiveDateTime;
+use scoring::ScoringStrategy;
+
+pub mod authors;
+pub mod backtest;
+pub mod diversity;
+pub mod scoring;
+pub mod trending;
+
+/// Post competing for the ranking with the score of the active strategy
+#[derive(Clone)]
+pub struct Candidate {
+    pub post: Post,
+    pub user: User,
+    pub score: f64,
+}
+
+/// Scores the posts with the strategy and sorts them best first, leaving out
+/// posts without a positive score.
+pub fn score_candidates(
+    strategy: &mut dyn ScoringStrategy,
+    posts_with_users: Vec<(Post, User)>,
+    now: NaiveDateTime,
+) -> Vec<Candidate> {
+    score_candidates_at(strategy, posts_with_users, |_| now)
+}
+
+/// Same as `score_candidates`, but every post is scored as of its own
+/// `ranked_at` time, so posts of different days are compared at the same age.
+pub fn score_candidates_at(
+    strategy: &mut dyn ScoringStrategy,
+    posts_with_users: Vec<(Post, User)>,
+    ranked_at: impl Fn(&Post) -> NaiveDateTime,
+) -> Vec<Candidate> {
+    let posts: Vec<&Post> = posts_with_users.iter().map(|(post, _)| post).collect();
+    strategy.prepare(&posts);
+
+    let mut candidates: Vec<Candidate> = posts_with_users
+        .into_iter()
+        .map(|(post, user)| Candidate {
+            score: strategy.score(&post, &user, ranked_at(&post)),
+            post,
+            user,
+        })
+        .filter(|candidate| candidate.score > 0.0)
+        .collect();
+
+    sort_by_score(&mut candidates);
+
+    candidates
+}
+
+/// Best first, ties keep the older post first as it reached the score earlier
+pub fn sort_by_score(candidates: &mut [Candidate]) {
+    candidates.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.post.created_at.cmp(&b.post.created_at))
+    });
+}
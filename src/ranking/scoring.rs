@@ -0,0 +1,272 @@
+use crate::{
+    config::{ScoringConfig, ScoringStrategyKind},
+    database::models::{Post, User},
+};
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+
+/// Raw engagement counters of a post
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counters {
+    pub likes: i32,
+    pub replies: i32,
+    pub reposts: i32,
+    pub quotes: i32,
+}
+
+impl From<&Post> for Counters {
+    fn from(post: &Post) -> Self {
+        Counters {
+            likes: post.like_count,
+            replies: post.reply_count,
+            reposts: post.repost_count,
+            quotes: post.quote_count,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Weights {
+    pub like: i32,
+    pub reply: i32,
+    pub repost: i32,
+    pub quote: i32,
+}
+
+impl Weights {
+    pub fn from_config(config: &ScoringConfig) -> Self {
+        Weights {
+            like: config.like_weight,
+            reply: config.reply_weight,
+            repost: config.repost_weight,
+            quote: config.quote_weight,
+        }
+    }
+
+    /// Saturates instead of overflowing on viral posts
+    pub fn points(&self, counters: &Counters) -> i32 {
+        counters
+            .likes
+            .saturating_mul(self.like)
+            .saturating_add(counters.replies.saturating_mul(self.reply))
+            .saturating_add(counters.reposts.saturating_mul(self.repost))
+            .saturating_add(counters.quotes.saturating_mul(self.quote))
+    }
+}
+
+/// Turns the stored counters of a post into its ranking score. Posts with a
+/// score of 0 or less are not ranked.
+pub trait ScoringStrategy {
+    /// Called once with every candidate before scoring, for strategies that
+    /// compare a post with others.
+    fn prepare(&mut self, _posts: &[&Post]) {}
+
+    fn score(&self, post: &Post, author: &User, now: NaiveDateTime) -> f64;
+}
+
+pub fn strategy_from_config(config: &ScoringConfig) -> Box<dyn ScoringStrategy> {
+    let weights = Weights::from_config(config);
+
+    match config.strategy {
+        ScoringStrategyKind::WeightedSum => Box::new(WeightedSum { weights }),
+        ScoringStrategyKind::TimeDecay => Box::new(TimeDecay {
+            weights,
+            gravity: config.gravity,
+        }),
+        ScoringStrategyKind::EngagementRate => Box::new(EngagementRate {
+            weights,
+            prior_posts: config.baseline_prior_posts,
+            community_baseline: 0.0,
+            author_baselines: HashMap::new(),
+        }),
+        ScoringStrategyKind::Log => Box::new(LogDamped { weights }),
+        ScoringStrategyKind::Audience => Box::new(Audience {
+            weights,
+            floor: config.audience_floor,
+        }),
+    }
+}
+
+/// Weighted sum of the counters, the original scoring of the bot
+pub struct WeightedSum {
+    weights: Weights,
+}
+
+impl ScoringStrategy for WeightedSum {
+    fn score(&self, post: &Post, _author: &User, _now: NaiveDateTime) -> f64 {
+        self.weights.points(&Counters::from(post)) as f64
+    }
+}
+
+/// Hacker News gravity: points divided by the age of the post raised to the
+/// `gravity` power, so a younger post needs fewer reactions to keep up.
+pub struct TimeDecay {
+    weights: Weights,
+    gravity: f64,
+}
+
+impl ScoringStrategy for TimeDecay {
+    fn score(&self, post: &Post, _author: &User, now: NaiveDateTime) -> f64 {
+        let points = self.weights.points(&Counters::from(post)) as f64;
+        let age_hours = (now - post.created_at).num_seconds().max(0) as f64 / 3600.0;
+
+        points / (age_hours + 2.0).powf(self.gravity)
+    }
+}
+
+/// Points relative to the usual engagement of the author, so a post that
+/// stands out for its author beats a routine post of a popular account. The
+/// author average is pulled towards the community average by `prior_posts`
+/// imaginary posts, otherwise a single post would always score 1.
+pub struct EngagementRate {
+    weights: Weights,
+    prior_posts: f64,
+    community_baseline: f64,
+    author_baselines: HashMap<i32, f64>,
+}
+
+impl ScoringStrategy for EngagementRate {
+    fn prepare(&mut self, posts: &[&Post]) {
+        let mut author_totals: HashMap<i32, (f64, f64)> = HashMap::new();
+        let mut total_points = 0.0;
+
+        for post in posts {
+            let points = self.weights.points(&Counters::from(*post)) as f64;
+            let (author_points, author_posts) = author_totals.entry(post.user_id).or_default();
+            *author_points += points;
+            *author_posts += 1.0;
+            total_points += points;
+        }
+
+        self.community_baseline = total_points / posts.len().max(1) as f64;
+        self.author_baselines = author_totals
+            .into_iter()
+            .map(|(user_id, (points, count))| {
+                let baseline = (points + self.prior_posts * self.community_baseline)
+                    / (count + self.prior_posts);
+                (user_id, baseline)
+            })
+            .collect();
+    }
+
+    fn score(&self, post: &Post, _author: &User, _now: NaiveDateTime) -> f64 {
+        let points = self.weights.points(&Counters::from(post)) as f64;
+        let baseline = self
+            .author_baselines
+            .get(&post.user_id)
+            .copied()
+            .unwrap_or(self.community_baseline);
+
+        if baseline <= 0.0 {
+            return points;
+        }
+
+        points / baseline
+    }
+}
+
+/// Weighted sum of log-damped counters, a post with a bit of every kind of
+/// engagement beats a post that went viral on one counter.
+pub struct LogDamped {
+    weights: Weights,
+}
+
+impl ScoringStrategy for LogDamped {
+    fn score(&self, post: &Post, _author: &User, _now: NaiveDateTime) -> f64 {
+        let counters = Counters::from(post);
+        let damped = |count: i32, weight: i32| (count.max(0) as f64).ln_1p() * weight as f64;
+
+        damped(counters.likes, self.weights.like)
+            + damped(counters.replies, self.weights.reply)
+            + damped(counters.reposts, self.weights.repost)
+            + damped(counters.quotes, self.weights.quote)
+    }
+}
+
+/// Points per thousand followers of the author. Accounts below `floor`
+/// followers count as having `floor` followers so a couple of likes on a tiny
+/// account can't win. Authors whose follower count is unknown yet keep a
+/// vanishing fraction of their points, ranked by points behind every author
+/// with a known audience.
+pub struct Audience {
+    weights: Weights,
+    floor: i32,
+}
+
+impl ScoringStrategy for Audience {
+    fn score(&self, post: &Post, author: &User, _now: NaiveDateTime) -> f64 {
+        let points = self.weights.points(&Counters::from(post)) as f64;
+        let Some(followers) = author.followers_count else {
+            // Far below 1000 / i32::MAX, the lowest score of a known audience
+            return points * f64::MIN_POSITIVE;
+        };
+
+        points * 1000.0 / followers.max(self.floor) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::ScoringConfig, ranking::score_candidates};
+
+    fn post(id: i32, user_id: i32, like_count: i32) -> Post {
+        Post {
+            id,
+            created_at: NaiveDateTime::default(),
+            user_id,
+            uri: format!("at://did:plc:{}/app.bsky.feed.post/{}", user_id, id),
+            cid: format!("cid-{}", id),
+            reply_count: 0,
+            repost_count: 0,
+            like_count,
+            quote_count: 0,
+            total_points: like_count,
+            root_uri: None,
+            refreshed_at: None,
+            missed_refreshes: 0,
+            archived_at: None,
+        }
+    }
+
+    fn user(id: i32, followers_count: Option<i32>) -> User {
+        User {
+            id,
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+            did: format!("did:plc:{}", id),
+            handle: format!("user{}.bsky.social", id),
+            display_name: None,
+            followers_count,
+            posts_count: None,
+        }
+    }
+
+    #[test]
+    fn unknown_audience_ranks_last() {
+        let config = ScoringConfig {
+            strategy: ScoringStrategyKind::Audience,
+            ..ScoringConfig::default()
+        };
+        let mut strategy = strategy_from_config(&config);
+
+        let posts_with_users = vec![
+            (post(1, 1, 500), user(1, None)),
+            (post(2, 2, 1), user(2, Some(i32::MAX))),
+            (post(3, 3, 20), user(3, None)),
+            (post(4, 4, 0), user(4, None)),
+            (post(5, 5, 10), user(5, Some(10))),
+        ];
+        let ranked: Vec<i32> = score_candidates(
+            strategy.as_mut(),
+            posts_with_users,
+            NaiveDateTime::default(),
+        )
+        .iter()
+        .map(|candidate| candidate.post.id)
+        .collect();
+
+        // Known audiences first, unknown ones by points, no engagement not at all
+        assert_eq!(ranked, vec![5, 2, 1, 3]);
+    }
+}
@@ -0,0 +1,108 @@
+use super::{sort_by_score, Candidate};
+use crate::database::models::{Post, PostMetricsSnapshot, User};
+use chrono::{Duration, NaiveDateTime};
+use std::collections::HashMap;
+
+/// Shortest span between two snapshots the growth is measured over
+const MIN_SPAN_MINUTES: i64 = 10;
+
+struct PostHistory {
+    post: Post,
+    user: User,
+    /// Oldest first
+    snapshots: Vec<PostMetricsSnapshot>,
+}
+
+/// Scores posts younger than `max_age` by how fast their points grew over the
+/// last `lookback`, relative to the usual pace of the author. The pace of a
+/// post is its points per hour of age at its latest snapshot, the author
+/// pace is their average smoothed towards the community average with
+/// `prior_posts` imaginary posts.
+pub fn score_trending(
+    history: Vec<(PostMetricsSnapshot, Post, User)>,
+    now: NaiveDateTime,
+    max_age: Duration,
+    lookback: Duration,
+    prior_posts: f64,
+) -> Vec<Candidate> {
+    let mut posts: HashMap<i32, PostHistory> = HashMap::new();
+    for (snapshot, post, user) in history {
+        posts
+            .entry(post.id)
+            .or_insert_with(|| PostHistory {
+                post,
+                user,
+                snapshots: Vec::new(),
+            })
+            .snapshots
+            .push(snapshot);
+    }
+
+    let mut author_paces: HashMap<i32, (f64, f64)> = HashMap::new();
+    let mut total_pace = 0.0;
+    for history in posts.values() {
+        let Some(latest) = history.snapshots.last() else {
+            continue;
+        };
+        let age_hours = hours(latest.created_at - history.post.created_at).max(0.25);
+        let pace = latest.total_points as f64 / age_hours;
+
+        let (author_pace, author_posts) = author_paces.entry(history.user.id).or_default();
+        *author_pace += pace;
+        *author_posts += 1.0;
+        total_pace += pace;
+    }
+    let community_pace = total_pace / posts.len().max(1) as f64;
+
+    let mut candidates: Vec<Candidate> = posts
+        .into_values()
+        .filter(|history| now - history.post.created_at <= max_age)
+        .filter_map(|history| {
+            let velocity = velocity(&history.snapshots, now - lookback)?;
+
+            let (author_pace, author_posts) = author_paces
+                .get(&history.user.id)
+                .copied()
+                .unwrap_or_default();
+            let usual_pace =
+                (author_pace + prior_posts * community_pace) / (author_posts + prior_posts);
+
+            let score = if usual_pace > 0.0 {
+                velocity / usual_pace
+            } else {
+                velocity
+            };
+
+            Some(Candidate {
+                post: history.post,
+                user: history.user,
+                score,
+            })
+        })
+        .filter(|candidate| candidate.score > 0.0)
+        .collect();
+
+    sort_by_score(&mut candidates);
+
+    candidates
+}
+
+/// Points per hour between the first snapshot taken after `since` and the
+/// latest one.
+fn velocity(snapshots: &[PostMetricsSnapshot], since: NaiveDateTime) -> Option<f64> {
+    let first = snapshots
+        .iter()
+        .find(|snapshot| snapshot.created_at >= since)?;
+    let latest = snapshots.last()?;
+
+    let span = latest.created_at - first.created_at;
+    if span < Duration::minutes(MIN_SPAN_MINUTES) {
+        return None;
+    }
+
+    Some((latest.total_points - first.total_points) as f64 / hours(span))
+}
+
+fn hours(duration: Duration) -> f64 {
+    duration.num_seconds() as f64 / 3600.0
+}
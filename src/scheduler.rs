@@ -0,0 +1,211 @@
+use crate::{
+    config::Config,
+    database::{self, models::RankingKind},
+    services::bsky::Bsky,
+    tasks,
+};
+use std::{sync::Arc, time::Duration};
+use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
+
+/// Runs the bot as a daemon: resumes an unfinished publication and starts the
+/// collection and publication jobs.
+pub async fn run(bsky: Bsky, config: Arc<Config>) -> Result<(), JobSchedulerError> {
+    println!("App is running");
+
+    if let Err(error) = tasks::sync_users(&bsky, &config).await {
+        println!("Error during syncing users: {}", error);
+    }
+
+    let kinds = [
+        RankingKind::Daily,
+        RankingKind::Trending,
+        RankingKind::Weekly,
+        RankingKind::Monthly,
+    ];
+    for kind in kinds {
+        let Ok(Some(ranking_id)) = database::queries::get_unfinished_ranking_id(kind.as_str())
+        else {
+            continue;
+        };
+
+        println!("Found unfinished publication {}", ranking_id);
+        if let Err(error) = bsky.ensure_session().await {
+            println!("Error during authentication to bsky: {}", error);
+            break;
+        }
+
+        let bsky = bsky.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let res = match kind {
+                RankingKind::Daily => tasks::post_top_ten(&bsky, &config).await,
+                RankingKind::Trending => tasks::post_trending(&bsky, &config).await,
+                RankingKind::Weekly | RankingKind::Monthly => {
+                    tasks::post_best_of(&bsky, &config, kind).await
+                }
+            };
+            if let Err(error) = res {
+                println!("Error during posting thread: {}", error);
+            }
+        });
+    }
+
+    if let Ok(Some(ranking_id)) =
+        database::queries::get_unfinished_ranking_id(tasks::LEADERBOARD_KIND)
+    {
+        println!("Found unfinished publication {}", ranking_id);
+        match bsky.ensure_session().await {
+            Ok(_) => {
+                let bsky = bsky.clone();
+                let config = config.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = tasks::post_authors_leaderboard(&bsky, &config).await {
+                        println!("Error during posting thread: {}", error);
+                    }
+                });
+            }
+            Err(error) => println!("Error during authentication to bsky: {}", error),
+        }
+    }
+
+    if config.firehose.enabled {
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(error) = tasks::run_firehose(&config).await {
+                println!("Error during counting firehose engagement: {}", error);
+            }
+        });
+    }
+
+    let mut sched = JobScheduler::new().await?;
+
+    let daily_bsky = bsky.clone();
+    let daily_config = config.clone();
+    let publish_schedule = config.schedule.publish.as_str();
+    sched
+        .add(Job::new_async(publish_schedule, move |uuid, mut l| {
+            let bsky = daily_bsky.clone();
+            let config = daily_config.clone();
+            Box::pin(async move {
+                if let Err(error) = bsky.ensure_session().await {
+                    println!("Error during authentication to bsky: {}", error);
+                }
+
+                if let Err(error) = tasks::sync_users(&bsky, &config).await {
+                    println!("Error during syncing users: {}", error);
+                }
+                if let Err(error) = tasks::post_top_ten(&bsky, &config).await {
+                    println!("Error during posting thread: {}", error);
+                }
+                l.next_tick_for_job(uuid).await.unwrap();
+            })
+        })?)
+        .await?;
+
+    let hourly_bsky = bsky.clone();
+    let hourly_config = config.clone();
+    let collect_schedule = config.schedule.collect.as_str();
+    sched
+        .add(Job::new_async(collect_schedule, move |uuid, mut l| {
+            let bsky = hourly_bsky.clone();
+            let config = hourly_config.clone();
+            Box::pin(async move {
+                if let Err(error) = tasks::collect_posts(&bsky, &config).await {
+                    println!("Error during syncing posts: {}", error);
+                }
+                l.next_tick_for_job(uuid).await.unwrap();
+            })
+        })?)
+        .await?;
+
+    if config.trending.enabled {
+        let trending_bsky = bsky.clone();
+        let trending_config = config.clone();
+        let trending_schedule = config.schedule.trending.as_str();
+        sched
+            .add(Job::new_async(trending_schedule, move |uuid, mut l| {
+                let bsky = trending_bsky.clone();
+                let config = trending_config.clone();
+                Box::pin(async move {
+                    if let Err(error) = bsky.ensure_session().await {
+                        println!("Error during authentication to bsky: {}", error);
+                    }
+
+                    if let Err(error) = tasks::post_trending(&bsky, &config).await {
+                        println!("Error during posting trending thread: {}", error);
+                    }
+                    l.next_tick_for_job(uuid).await.unwrap();
+                })
+            })?)
+            .await?;
+    }
+
+    let periods = [
+        (RankingKind::Weekly, &config.weekly, &config.schedule.weekly),
+        (
+            RankingKind::Monthly,
+            &config.monthly,
+            &config.schedule.monthly,
+        ),
+    ];
+    for (kind, period, schedule) in periods {
+        if !period.enabled {
+            continue;
+        }
+
+        let period_bsky = bsky.clone();
+        let period_config = config.clone();
+        sched
+            .add(Job::new_async(schedule.as_str(), move |uuid, mut l| {
+                let bsky = period_bsky.clone();
+                let config = period_config.clone();
+                Box::pin(async move {
+                    if let Err(error) = bsky.ensure_session().await {
+                        println!("Error during authentication to bsky: {}", error);
+                    }
+
+                    if let Err(error) = tasks::post_best_of(&bsky, &config, kind).await {
+                        println!("Error during posting {} thread: {}", kind.as_str(), error);
+                    }
+                    l.next_tick_for_job(uuid).await.unwrap();
+                })
+            })?)
+            .await?;
+    }
+
+    if config.leaderboard.enabled {
+        let leaderboard_bsky = bsky.clone();
+        let leaderboard_config = config.clone();
+        let leaderboard_schedule = config.schedule.leaderboard.as_str();
+        sched
+            .add(Job::new_async(leaderboard_schedule, move |uuid, mut l| {
+                let bsky = leaderboard_bsky.clone();
+                let config = leaderboard_config.clone();
+                Box::pin(async move {
+                    if let Err(error) = bsky.ensure_session().await {
+                        println!("Error during authentication to bsky: {}", error);
+                    }
+
+                    if let Err(error) = tasks::post_authors_leaderboard(&bsky, &config).await {
+                        println!("Error during posting authors leaderboard thread: {}", error);
+                    }
+                    l.next_tick_for_job(uuid).await.unwrap();
+                })
+            })?)
+            .await?;
+    }
+
+    sched.shutdown_on_ctrl_c();
+
+    sched.set_shutdown_handler(Box::new(|| {
+        Box::pin(async move {
+            println!("App was closed");
+        })
+    }));
+
+    sched.start().await?;
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(31536000)).await;
+    }
+}
@@ -3,16 +3,16 @@ use crate::database::models::User;
 use regex::bytes::Regex;
 use std::{collections::HashMap, ops::Deref, sync::LazyLock};
 
-const URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"(https?:\/\/(www\.)?[-a-zA-Z0-9@:%._\+~#=]{1,256}\.[a-zA-Z0-9()]{1,6}\b([-a-zA-Z0-9()@:%_\+.~#?&//=]*[-a-zA-Z0-9@%_\+~#//=])?)")
         .unwrap()
 });
 
-const MENTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+static MENTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"(@([a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?\.)+[a-zA-Z]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)").unwrap()
 });
 
-const TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?:^|\s)(#[^\d\s]\S*)").unwrap());
+static TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?:^|\s)(#[^\d\s]\S*)").unwrap());
 
 struct Span {
     start: u64,
@@ -24,19 +24,15 @@ fn parse_tags(text: &str) -> Vec<Span> {
     let text_bytes = text.as_bytes();
     let mut spans: Vec<Span> = Vec::new();
 
-    for m in TAG_REGEX.find_iter(text_bytes) {
-        let start = m.start() as u64;
-        let end = m.end() as u64;
-
-        if let Some(capture) = TAG_REGEX.captures(m.as_bytes()) {
-            let tag = capture.get(1).unwrap().as_bytes()[1..].to_vec();
-            let tag = String::from_utf8(tag).unwrap();
-            spans.push(Span {
-                start,
-                end,
-                span: tag,
-            });
-        }
+    for capture in TAG_REGEX.captures_iter(text_bytes) {
+        // The match includes the whitespace before the tag, the facet covers the tag only
+        let tag_match = capture.get(1).unwrap();
+        let tag = String::from_utf8(tag_match.as_bytes()[1..].to_vec()).unwrap();
+        spans.push(Span {
+            start: tag_match.start() as u64,
+            end: tag_match.end() as u64,
+            span: tag,
+        });
     }
 
     spans
@@ -84,10 +80,10 @@ fn parse_urls(text: &str) -> Vec<Span> {
     spans
 }
 
-pub fn parse_facets_with_users(text: &String, users: &Vec<User>) -> Vec<Facet> {
-    let mention_spans = parse_mentions(text.as_str());
-    let url_spans = parse_urls(text.as_str());
-    let tag_spans = parse_tags(text.as_str());
+pub fn parse_facets_with_users(text: &str, users: &[User]) -> Vec<Facet> {
+    let mention_spans = parse_mentions(text);
+    let url_spans = parse_urls(text);
+    let tag_spans = parse_tags(text);
 
     let handles_map: HashMap<&String, &String> =
         users.iter().map(|user| (&user.handle, &user.did)).collect();
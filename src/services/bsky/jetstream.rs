@@ -0,0 +1,289 @@
+use super::structs::Error;
+use futures::StreamExt;
+use reqwest::Url;
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+/// Collections whose records count as engagement with a post
+const WANTED_COLLECTIONS: [&str; 3] = [
+    "app.bsky.feed.like",
+    "app.bsky.feed.repost",
+    "app.bsky.feed.post",
+];
+
+#[derive(Deserialize)]
+pub struct Event {
+    /// Time the event was received by Jetstream, in microseconds, used as the
+    /// cursor of the subscription
+    pub time_us: i64,
+    pub commit: Option<Commit>,
+}
+
+#[derive(Deserialize)]
+pub struct Commit {
+    pub operation: String,
+    pub record: Option<EventRecord>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "$type")]
+pub enum EventRecord {
+    #[serde(rename = "app.bsky.feed.like")]
+    Like { subject: Subject },
+    #[serde(rename = "app.bsky.feed.repost")]
+    Repost { subject: Subject },
+    #[serde(rename = "app.bsky.feed.post")]
+    Post {
+        reply: Option<ReplyRef>,
+        embed: Option<EmbedRef>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+pub struct Subject {
+    pub uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReplyRef {
+    pub parent: Subject,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "$type")]
+pub enum EmbedRef {
+    #[serde(rename = "app.bsky.embed.record")]
+    Record { record: Subject },
+    #[serde(rename = "app.bsky.embed.recordWithMedia")]
+    RecordWithMedia { record: EmbeddedRecord },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+pub struct EmbeddedRecord {
+    pub record: Subject,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Engagement {
+    Like,
+    Repost,
+    Reply,
+    Quote,
+}
+
+impl Event {
+    /// Engagements created by the event, paired with the URI of the post they
+    /// count towards. Deletions are not reported, Jetstream doesn't say what
+    /// the deleted record pointed to.
+    pub fn engagements(&self) -> Vec<(Engagement, &str)> {
+        let Some(commit) = &self.commit else {
+            return vec![];
+        };
+        if commit.operation != "create" {
+            return vec![];
+        }
+
+        match &commit.record {
+            Some(EventRecord::Like { subject }) => vec![(Engagement::Like, subject.uri.as_str())],
+            Some(EventRecord::Repost { subject }) => {
+                vec![(Engagement::Repost, subject.uri.as_str())]
+            }
+            Some(EventRecord::Post { reply, embed }) => {
+                let mut engagements = vec![];
+                if let Some(reply) = reply {
+                    engagements.push((Engagement::Reply, reply.parent.uri.as_str()));
+                }
+                match embed {
+                    Some(EmbedRef::Record { record })
+                    | Some(EmbedRef::RecordWithMedia {
+                        record: EmbeddedRecord { record },
+                    }) => engagements.push((Engagement::Quote, record.uri.as_str())),
+                    _ => {}
+                }
+                engagements
+            }
+            Some(EventRecord::Other) | None => vec![],
+        }
+    }
+}
+
+/// DID of the author of the post behind the URI, `None` for other records.
+pub fn post_author(uri: &str) -> Option<&str> {
+    let mut parts = uri.strip_prefix("at://")?.split('/');
+    let did = parts.next()?;
+
+    (parts.next()? == "app.bsky.feed.post").then_some(did)
+}
+
+pub fn parse_event(text: &str) -> Result<Event, Error> {
+    serde_json::from_str(text).map_err(|err| Error::DecodeError(String::from("jetstream"), err))
+}
+
+pub struct Subscription {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl Subscription {
+    /// Subscribes to the engagement collections, replaying events received
+    /// since `cursor` when it is given.
+    pub async fn connect(endpoint: &str, cursor: Option<i64>) -> Result<Subscription, Error> {
+        let mut url = Url::parse(endpoint)
+            .map_err(|err| Error::DataError(format!("invalid url {}: {}", endpoint, err)))?;
+        {
+            let mut query = url.query_pairs_mut();
+            for collection in WANTED_COLLECTIONS {
+                query.append_pair("wantedCollections", collection);
+            }
+            if let Some(cursor) = cursor {
+                query.append_pair("cursor", &cursor.to_string());
+            }
+        }
+
+        let (socket, _) = connect_async(url.as_str())
+            .await
+            .map_err(Error::WebSocketError)?;
+
+        Ok(Subscription { socket })
+    }
+
+    /// Next event of the stream, `None` once the server closed the connection.
+    pub async fn next_event(&mut self) -> Option<Result<Event, Error>> {
+        loop {
+            let message = match self.socket.next().await? {
+                Ok(message) => message,
+                Err(err) => return Some(Err(Error::WebSocketError(err))),
+            };
+
+            match message {
+                Message::Text(text) => return Some(parse_event(text.as_str())),
+                Message::Close(_) => return None,
+                // Pings are answered by tungstenite itself
+                _ => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POST: &str = "at://did:plc:alice/app.bsky.feed.post/thread";
+    const QUOTED: &str = "at://did:plc:bob/app.bsky.feed.post/quoted";
+
+    fn commit_event(operation: &str, record: &str) -> Event {
+        parse_event(&format!(
+            r#"{{"did":"did:plc:erin","time_us":42,"kind":"commit","commit":{{"operation":"{}","record":{}}}}}"#,
+            operation, record
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn likes_and_reposts_count_for_their_subject() {
+        let like = commit_event(
+            "create",
+            &format!(
+                r#"{{"$type":"app.bsky.feed.like","subject":{{"uri":"{}"}}}}"#,
+                POST
+            ),
+        );
+        let repost = commit_event(
+            "create",
+            &format!(
+                r#"{{"$type":"app.bsky.feed.repost","subject":{{"uri":"{}"}}}}"#,
+                POST
+            ),
+        );
+
+        assert_eq!(like.time_us, 42);
+        assert_eq!(like.engagements(), vec![(Engagement::Like, POST)]);
+        assert_eq!(repost.engagements(), vec![(Engagement::Repost, POST)]);
+    }
+
+    #[test]
+    fn post_counts_as_reply_and_quote() {
+        let event = commit_event(
+            "create",
+            &format!(
+                r#"{{"$type":"app.bsky.feed.post","text":"","reply":{{"root":{{"uri":"{0}"}},"parent":{{"uri":"{0}"}}}},"embed":{{"$type":"app.bsky.embed.record","record":{{"uri":"{1}"}}}}}}"#,
+                POST, QUOTED
+            ),
+        );
+
+        assert_eq!(
+            event.engagements(),
+            vec![(Engagement::Reply, POST), (Engagement::Quote, QUOTED)]
+        );
+    }
+
+    #[test]
+    fn quote_with_media_counts() {
+        let event = commit_event(
+            "create",
+            &format!(
+                r#"{{"$type":"app.bsky.feed.post","text":"","embed":{{"$type":"app.bsky.embed.recordWithMedia","record":{{"record":{{"uri":"{}"}}}},"media":{{}}}}}}"#,
+                QUOTED
+            ),
+        );
+
+        assert_eq!(event.engagements(), vec![(Engagement::Quote, QUOTED)]);
+    }
+
+    #[test]
+    fn other_events_count_for_nothing() {
+        let plain_post = commit_event(
+            "create",
+            r#"{"$type":"app.bsky.feed.post","text":"","embed":{"$type":"app.bsky.embed.images","images":[]}}"#,
+        );
+        let follow = commit_event(
+            "create",
+            r#"{"$type":"app.bsky.graph.follow","subject":"did:plc:alice"}"#,
+        );
+        let update = commit_event(
+            "update",
+            &format!(
+                r#"{{"$type":"app.bsky.feed.like","subject":{{"uri":"{}"}}}}"#,
+                POST
+            ),
+        );
+        let deletion = parse_event(
+            r#"{"did":"did:plc:erin","time_us":43,"kind":"commit","commit":{"operation":"delete"}}"#,
+        )
+        .unwrap();
+        let identity =
+            parse_event(r#"{"did":"did:plc:erin","time_us":44,"kind":"identity"}"#).unwrap();
+
+        for event in [plain_post, follow, update, deletion, identity] {
+            assert!(event.engagements().is_empty());
+        }
+    }
+
+    #[test]
+    fn broken_event_is_a_decode_error() {
+        assert!(matches!(
+            parse_event(r#"{"did":"did:plc:erin","kind":"commit"}"#),
+            Err(Error::DecodeError(_, _))
+        ));
+        assert!(matches!(
+            parse_event("{\"time_us\":"),
+            Err(Error::DecodeError(_, _))
+        ));
+    }
+
+    #[test]
+    fn post_author_of_post_uris_only() {
+        assert_eq!(post_author(POST), Some("did:plc:alice"));
+        assert_eq!(
+            post_author("at://did:plc:alice/app.bsky.feed.generator/news"),
+            None
+        );
+        assert_eq!(post_author("at://did:plc:alice"), None);
+        assert_eq!(post_author("https://bsky.app/profile/alice"), None);
+    }
+}
@@ -1,220 +1,562 @@
 use chrono::{SecondsFormat, Utc};
-use reqwest::{Client, StatusCode};
-use std::env;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use std::{
+    env,
+    fs::OpenOptions,
+    future::Future,
+    io::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+};
+use tokio::sync::Mutex;
 
 pub mod facets;
+pub mod jetstream;
+pub mod recording;
 pub mod structs;
+pub mod xrpc;
+
+use recording::{Recorder, Replayer};
+use xrpc::{RateLimits, RetryPolicy};
+
+/// Max number of URIs accepted by `getPosts`
+pub const GET_POSTS_LIMIT: usize = 25;
+/// Max number of actors accepted by `getProfiles`
+pub const GET_PROFILES_LIMIT: usize = 25;
+/// Max length of a post text in graphemes
+pub const MAX_POST_GRAPHEMES: usize = 300;
+/// Alphabet of the base32-sortable encoding of record keys
+const TID_ALPHABET: &[u8; 32] = b"234567abcdefghijklmnopqrstuvwxyz";
 
 use structs::{
-    AuthenticationBody, AuthenticationResponse, BskyError, Embed, Error, Facet, FeedListOptions,
-    FeedResponse, FollowersListOptions, FollowsResponse, NewPost, NewRecord, PostRef, RecordType,
-    Reply,
+    AuthenticationBody, AuthenticationResponse, Author, BskyError, BskyErrorCode, Embed, Error,
+    Facet, FeedListOptions, FeedResponse, FollowersListOptions, FollowsResponse, NewPost,
+    NewRecord, Post, PostRef, PostsResponse, ProfilesResponse, RecordType, Reply,
 };
 
+/// Default host of the session, it hands out tokens valid on the PDS of the account
+pub const DEFAULT_ENTRYWAY: &str = "https://bsky.social";
+/// Default host of the public read endpoints
+pub const DEFAULT_APPVIEW: &str = "https://public.api.bsky.app";
+
+struct Session {
+    access_jwt: String,
+    refresh_jwt: String,
+    did: String,
+    /// Host of the repo writes of the session
+    pds: String,
+}
+
+/// Instead of posting, dumps every `createRecord` payload to the output file
+/// (one JSON per line) or to stdout.
+struct DryRun {
+    output: Option<PathBuf>,
+    posts_count: AtomicUsize,
+}
+
 #[derive(Clone)]
 pub struct Bsky {
-    access_jwt: Option<String>,
-    refresh_jwt: Option<String>,
-    handle: Option<String>,
-    did: Option<String>,
+    lang: String,
+    dry_run: Option<Arc<DryRun>>,
+    session: Arc<RwLock<Option<Session>>>,
+    refresh_lock: Arc<Mutex<()>>,
+    rate_limits: Arc<RateLimits>,
     client: Client,
+    entryway: String,
+    pds: Option<String>,
+    appview: String,
+    recorder: Option<Arc<Recorder>>,
+    replayer: Option<Arc<Replayer>>,
 }
 
-impl Bsky {
-    pub fn new() -> Self {
+pub struct BskyBuilder {
+    lang: String,
+    dry_run: Option<DryRun>,
+    recorder: Option<Recorder>,
+    replayer: Option<Replayer>,
+    max_requests_per_second: f64,
+    entryway: String,
+    pds: Option<String>,
+    appview: String,
+}
+
+impl BskyBuilder {
+    pub fn lang(mut self, lang: &str) -> Self {
+        self.lang = String::from(lang);
+        self
+    }
+
+    pub fn dry_run(mut self, output: Option<PathBuf>) -> Self {
+        self.dry_run = Some(DryRun {
+            output,
+            posts_count: AtomicUsize::new(0),
+        });
+        self
+    }
+
+    /// Upper bound of requests per second shared by all clones of the client,
+    /// 0 disables the limit.
+    pub fn max_requests_per_second(mut self, rate: f64) -> Self {
+        self.max_requests_per_second = rate;
+        self
+    }
+
+    /// Host the session is created on when no PDS is set
+    pub fn entryway(mut self, url: &str) -> Self {
+        self.entryway = base_url(url);
+        self
+    }
+
+    /// Host of the session and the repo writes. When not set, writes go to the
+    /// PDS from the DID document of the account, or to the entryway.
+    pub fn pds(mut self, url: Option<&str>) -> Self {
+        self.pds = url.map(base_url);
+        self
+    }
+
+    /// Host of the public read endpoints
+    pub fn appview(mut self, url: &str) -> Self {
+        self.appview = base_url(url);
+        self
+    }
+
+    /// Saves every XRPC response to the fixture directory
+    pub fn record(mut self, dir: PathBuf) -> Self {
+        self.recorder = Some(Recorder::new(dir));
+        self
+    }
+
+    /// Serves recorded XRPC responses instead of calling the hosts
+    pub fn replay(mut self, replayer: Replayer) -> Self {
+        self.replayer = Some(replayer);
+        self
+    }
+
+    pub fn build(self) -> Bsky {
         let client = reqwest::Client::new();
         Bsky {
-            access_jwt: None,
-            refresh_jwt: None,
-            handle: None,
-            did: None,
+            lang: self.lang,
+            dry_run: self.dry_run.map(Arc::new),
+            session: Arc::new(RwLock::new(None)),
+            refresh_lock: Arc::new(Mutex::new(())),
+            rate_limits: Arc::new(RateLimits::with_max_rate(self.max_requests_per_second)),
             client,
+            entryway: self.entryway,
+            pds: self.pds,
+            appview: self.appview,
+            recorder: self.recorder.map(Arc::new),
+            replayer: self.replayer.map(Arc::new),
         }
     }
+}
 
-    pub fn is_authenticated(&self) -> bool {
-        self.access_jwt.is_some()
+impl Bsky {
+    pub fn builder() -> BskyBuilder {
+        BskyBuilder {
+            lang: String::from("ua"),
+            dry_run: None,
+            recorder: None,
+            replayer: None,
+            max_requests_per_second: 0.0,
+            entryway: String::from(DEFAULT_ENTRYWAY),
+            pds: None,
+            appview: String::from(DEFAULT_APPVIEW),
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.is_some()
     }
 
-    pub async fn authenticate(&mut self) -> Result<(), Error> {
-        let bsky_handle = env::var("BLUESKY_HANDLE").expect("BLUESKY_HANDLE must be set.");
-        let bsky_pass = env::var("BLUESKY_PASSWORD").expect("BLUESKY_PASSWORD must be set.");
+    pub async fn authenticate(&self) -> Result<(), Error> {
+        let bsky_handle = env::var("BLUESKY_HANDLE")
+            .map_err(|_| Error::AuthError(String::from("BLUESKY_HANDLE must be set")))?;
+        let bsky_pass = env::var("BLUESKY_PASSWORD")
+            .map_err(|_| Error::AuthError(String::from("BLUESKY_PASSWORD must be set")))?;
 
         let body = AuthenticationBody {
             identifier: bsky_handle,
             password: bsky_pass,
         };
 
+        let url = xrpc_url(&self.entryway, "com.atproto.server.createSession");
+        let res = self
+            .call::<AuthenticationResponse, _>("createSession", &RetryPolicy::WRITE, || {
+                self.client.post(&url).json(&body)
+            })
+            .await?;
+
+        self.store_session(res)
+    }
+
+    /// Refreshes the session on the entryway that created it
+    pub async fn refresh(&self) -> Result<(), Error> {
+        let refresh_jwt = self
+            .session
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|session| session.refresh_jwt.clone());
+
+        let Some(token) = refresh_jwt else {
+            return self.authenticate().await;
+        };
+
+        let url = xrpc_url(&self.entryway, "com.atproto.server.refreshSession");
         let res = self
-            .client
-            .post("https://bsky.social/xrpc/com.atproto.server.createSession")
-            .json(&body)
-            .send()
+            .call::<AuthenticationResponse, _>("refreshSession", &RetryPolicy::WRITE, || {
+                self.client.post(&url).bearer_auth(&token)
+            })
             .await;
 
-        if res.is_ok() {
-            let res = res.unwrap();
-            let status = res.status();
+        match res {
+            // Refresh token is dead as well, the only way back is a new session
+            Err(Error::BskyError(BskyError {
+                error: BskyErrorCode::ExpiredToken | BskyErrorCode::InvalidToken,
+                ..
+            })) => self.authenticate().await,
+            res => self.store_session(res?),
+        }
+    }
 
-            if status != StatusCode::OK {
-                return Err(Error::BskyError(res.json::<BskyError>().await.unwrap()));
-            }
+    fn store_session(&self, res: AuthenticationResponse) -> Result<(), Error> {
+        if let Some(status) = res.status {
+            return Err(Error::AuthError(format!(
+                "Unexpected issue with account: {}",
+                status
+            )));
+        }
+
+        let pds = match (
+            &self.pds,
+            res.did_doc.as_ref().and_then(|doc| doc.pds_endpoint()),
+        ) {
+            (Some(pds), _) => pds.clone(),
+            (None, Some(endpoint)) => base_url(endpoint),
+            (None, None) => self.entryway.clone(),
+        };
 
-            let res = res.json::<AuthenticationResponse>().await.unwrap();
+        *self.session.write().unwrap() = Some(Session {
+            access_jwt: res.access_jwt,
+            refresh_jwt: res.refresh_jwt,
+            did: res.did,
+            pds,
+        });
 
-            if res.status.is_some() {
-                panic!("Unexpected issue with account: {}", res.status.unwrap())
-            }
+        Ok(())
+    }
+
+    fn access_credentials(&self) -> Option<(String, String)> {
+        self.session
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|session| (session.access_jwt.clone(), session.did.clone()))
+    }
+
+    /// Host of the repo writes, known once the session is created
+    fn session_pds(&self) -> String {
+        self.session
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|session| session.pds.clone())
+            .unwrap_or_else(|| self.pds.clone().unwrap_or_else(|| self.entryway.clone()))
+    }
+
+    fn session_credentials(&self) -> Result<(String, String), Error> {
+        self.access_credentials()
+            .ok_or_else(|| Error::AuthError(String::from("Session was not initialized")))
+    }
 
-            self.access_jwt = Some(res.access_jwt);
-            self.refresh_jwt = Some(res.refresh_jwt);
-            self.handle = Some(res.handle);
-            self.did = Some(res.did);
+    /// Creates a session unless there is one already. Jobs starting at the
+    /// same time wait for each other instead of replacing each other's session.
+    pub async fn ensure_session(&self) -> Result<(), Error> {
+        if self.access_credentials().is_some() {
+            return Ok(());
+        }
 
-            Ok(())
-        } else {
-            Err(Error::ReqwestError(res.err().unwrap()))
+        let _guard = self.refresh_lock.lock().await;
+        if self.access_credentials().is_none() {
+            self.authenticate().await?;
         }
+
+        Ok(())
     }
 
-    // pub async fn refresh(&mut self) -> Result<(), Error> {
-    //     let token = self
-    //         .refresh_jwt
-    //         .as_ref()
-    //         .expect("Refresh Token was not initialized");
-    //     let res = self
-    //         .client
-    //         .post("https://public.api.bsky.app/xrpc/com.atproto.server.refreshSession")
-    //         .bearer_auth(&token)
-    //         .send()
-    //         .await;
+    /// Refreshes the session unless another task already did it since `stale_token` was issued.
+    async fn refresh_session(&self, stale_token: &str) -> Result<(), Error> {
+        let _guard = self.refresh_lock.lock().await;
 
-    //     if res.is_ok() {
-    //         let res = res.unwrap();
-    //         let status = res.status();
+        match self.access_credentials() {
+            Some((token, _)) if token != stale_token => Ok(()),
+            _ => self.refresh().await,
+        }
+    }
 
-    //         if status != StatusCode::OK {
-    //             return Err(Error::BskyError(res.json::<BskyError>().await.unwrap()));
-    //         }
+    /// Runs an authorized call with the current access token and repeats it once
+    /// with a refreshed session when the token turns out to be expired.
+    async fn with_session<T, F, Fut>(&self, call: F) -> Result<T, Error>
+    where
+        F: Fn(String, String) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        self.ensure_session().await?;
 
-    //         let res = res.json::<AuthenticationResponse>().await.unwrap();
+        let (token, did) = self.session_credentials()?;
 
-    //         if res.status.is_some() {
-    //             panic!("Unexpected issue with account: {}", res.status.unwrap())
-    //         }
+        match call(token.clone(), did).await {
+            Err(Error::BskyError(error)) if error.error == BskyErrorCode::ExpiredToken => {
+                self.refresh_session(&token).await?;
 
-    //         self.access_jwt = Some(res.access_jwt);
-    //         self.refresh_jwt = Some(res.refresh_jwt);
-    //         self.handle = Some(res.handle);
-    //         self.did = Some(res.did);
+                let (token, did) = self.session_credentials()?;
 
-    //         Ok(())
-    //     } else {
-    //         self.authenticate().await
-    //     }
-    // }
+                call(token, did).await
+            }
+            res => res,
+        }
+    }
 
     pub async fn get_actor_followers(
         &self,
         options: &FollowersListOptions,
     ) -> Result<FollowsResponse, Error> {
-        let res = self
-            .client
-            .get("https://public.api.bsky.app/xrpc/app.bsky.graph.getFollows")
-            .query(&options)
-            .send()
-            .await;
-
-        if res.is_ok() {
-            let res = res.unwrap();
-            let status = res.status();
+        let url = xrpc_url(&self.appview, "app.bsky.graph.getFollows");
+        self.call::<FollowsResponse, _>("getFollows", &RetryPolicy::READ, || {
+            self.client.get(&url).query(&options)
+        })
+        .await
+    }
 
-            if status != StatusCode::OK {
-                return Err(Error::BskyError(res.json::<BskyError>().await.unwrap()));
-            }
+    /// Detailed profiles with follower and post counts. Accepts up to
+    /// `GET_PROFILES_LIMIT` DIDs or handles.
+    pub async fn get_profiles(&self, actors: &[String]) -> Result<Vec<Author>, Error> {
+        let query: Vec<(&str, &str)> = actors
+            .iter()
+            .map(|actor| ("actors", actor.as_str()))
+            .collect();
+
+        let url = xrpc_url(&self.appview, "app.bsky.actor.getProfiles");
+        self.call::<ProfilesResponse, _>("getProfiles", &RetryPolicy::READ, || {
+            self.client.get(&url).query(&query)
+        })
+        .await
+        .map(|res| res.profiles)
+    }
 
-            let res = res.json::<FollowsResponse>().await.unwrap();
+    pub async fn get_author_feed(&self, options: &FeedListOptions) -> Result<FeedResponse, Error> {
+        let url = xrpc_url(&self.appview, "app.bsky.feed.getAuthorFeed");
+        self.call::<FeedResponse, _>("getAuthorFeed", &RetryPolicy::READ, || {
+            self.client.get(&url).query(&options)
+        })
+        .await
+    }
 
-            Ok(res)
-        } else {
-            Err(Error::ReqwestError(res.err().unwrap()))
-        }
+    /// Current views of the posts, deleted or hidden posts are left out of the
+    /// response. Accepts up to `GET_POSTS_LIMIT` URIs.
+    pub async fn get_posts(&self, uris: &[String]) -> Result<Vec<Post>, Error> {
+        let query: Vec<(&str, &str)> = uris.iter().map(|uri| ("uris", uri.as_str())).collect();
+
+        let url = xrpc_url(&self.appview, "app.bsky.feed.getPosts");
+        self.call::<PostsResponse, _>("getPosts", &RetryPolicy::READ, || {
+            self.client.get(&url).query(&query)
+        })
+        .await
+        .map(|res| res.posts)
     }
 
-    pub async fn get_author_feed(&self, options: &FeedListOptions) -> Result<FeedResponse, Error> {
+    /// Reference of the bot post with the record key, `None` when the repo
+    /// has no such record.
+    pub async fn get_own_post(&self, rkey: &str) -> Result<Option<PostRef>, Error> {
         let res = self
-            .client
-            .get("https://public.api.bsky.app/xrpc/app.bsky.feed.getAuthorFeed")
-            .query(&options)
-            .send()
+            .with_session(|token, did| {
+                let query = [
+                    ("repo", did),
+                    ("collection", String::from("app.bsky.feed.post")),
+                    ("rkey", rkey.to_string()),
+                ];
+                let url = xrpc_url(&self.session_pds(), "com.atproto.repo.getRecord");
+
+                async move {
+                    self.call::<PostRef, _>("getRecord", &RetryPolicy::READ, || {
+                        self.client.get(&url).bearer_auth(&token).query(&query)
+                    })
+                    .await
+                }
+            })
             .await;
 
-        if res.is_ok() {
-            let res = res.unwrap();
-            let status = res.status();
-
-            if status != StatusCode::OK {
-                return Err(Error::BskyError(res.json::<BskyError>().await.unwrap()));
-            }
-
-            let res = res.json::<FeedResponse>().await.unwrap();
-
-            Ok(res)
-        } else {
-            Err(Error::ReqwestError(res.err().unwrap()))
+        match res {
+            Err(Error::BskyError(BskyError {
+                error: BskyErrorCode::RecordNotFound,
+                ..
+            })) => Ok(None),
+            res => res.map(Some),
         }
     }
 
+    /// Publishes the post. With `rkey` the record is created under that key,
+    /// so a retry after a lost response can not post it twice.
     pub async fn create_post(
         &self,
         message: String,
         facets: Option<Vec<Facet>>,
         reply: Option<Reply>,
         embed: Option<Embed>,
+        rkey: Option<&str>,
     ) -> Result<PostRef, Error> {
-        let did = self.did.clone().expect("Session was not initialized");
+        let record = NewRecord {
+            record_type: RecordType::Post,
+            text: message,
+            created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            facets,
+            reply,
+            embed,
+        };
+
+        if let Some(dry_run) = &self.dry_run {
+            return self.dry_run_post(dry_run, record, rkey);
+        }
 
-        let test_post = NewPost {
-            repo: did,
+        self.with_session(|token, did| {
+            let new_post = NewPost {
+                repo: did,
+                collection: RecordType::Post,
+                rkey: rkey.map(String::from),
+                lang: vec![self.lang.clone()],
+                validate: true,
+                record: record.clone(),
+            };
+
+            let url = xrpc_url(&self.session_pds(), "com.atproto.repo.createRecord");
+
+            async move {
+                self.call::<PostRef, _>("createRecord", &RetryPolicy::WRITE, || {
+                    self.client.post(&url).bearer_auth(&token).json(&new_post)
+                })
+                .await
+            }
+        })
+        .await
+    }
+
+    /// Sends the XRPC request and decodes the response. Recorded responses
+    /// are served instead when replaying, and saved when recording.
+    async fn call<T, F>(&self, endpoint: &str, policy: &RetryPolicy, request: F) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+        F: Fn() -> RequestBuilder,
+    {
+        if let Some(replayer) = &self.replayer {
+            let request = request().build().map_err(Error::ReqwestError)?;
+            let (status, body) = replayer.response(endpoint, &request)?;
+            return decode_response(endpoint, status, &body);
+        }
+
+        let res = xrpc::send(&self.rate_limits, endpoint, policy, &request).await?;
+        let status = res.status();
+        let body = res.text().await.map_err(Error::ReqwestError)?;
+
+        if let Some(recorder) = &self.recorder {
+            // The builder is rebuilt to capture the request exactly as it was sent
+            let recorded = request()
+                .build()
+                .map_err(Error::ReqwestError)
+                .and_then(|request| recorder.record(endpoint, &request, status, &body));
+            if let Err(err) = recorded {
+                println!("Unable to record the response of {}: {}", endpoint, err);
+            }
+        }
+
+        decode_response(endpoint, status, &body)
+    }
+
+    fn dry_run_post(
+        &self,
+        dry_run: &DryRun,
+        record: NewRecord,
+        rkey: Option<&str>,
+    ) -> Result<PostRef, Error> {
+        let repo = match self.access_credentials() {
+            Some((_, did)) => did,
+            None => env::var("BLUESKY_HANDLE").unwrap_or_else(|_| String::from("dry-run")),
+        };
+
+        let new_post = NewPost {
+            repo: repo.clone(),
             collection: RecordType::Post,
-            lang: vec![String::from("ua")],
+            rkey: rkey.map(String::from),
+            lang: vec![self.lang.clone()],
             validate: true,
-            record: NewRecord {
-                record_type: RecordType::Post,
-                text: message,
-                created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
-                facets,
-                reply,
-                embed,
-            },
+            record,
         };
 
-        let token = self
-            .access_jwt
-            .as_ref()
-            .expect("Access Token was not initialized");
+        match &dry_run.output {
+            Some(path) => {
+                let payload =
+                    serde_json::to_string(&new_post).expect("Post payload is serializable");
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(Error::IoError)?;
+                writeln!(file, "{}", payload).map_err(Error::IoError)?;
+            }
+            None => {
+                let payload =
+                    serde_json::to_string_pretty(&new_post).expect("Post payload is serializable");
+                println!("Dry run, createRecord payload:\n{}", payload);
+            }
+        }
 
-        let res = self
-            .client
-            .post("https://bsky.social/xrpc/com.atproto.repo.createRecord")
-            .bearer_auth(&token)
-            .json(&test_post)
-            .send()
-            .await;
+        let count = dry_run.posts_count.fetch_add(1, Ordering::SeqCst) + 1;
 
-        if res.is_ok() {
-            let res = res.unwrap();
-            let status = res.status();
+        Ok(PostRef {
+            uri: format!("at://{}/app.bsky.feed.post/dry-run-{}", repo, count),
+            cid: format!("dry-run-{}", count),
+        })
+    }
+}
 
-            if status != StatusCode::OK {
-                return Err(Error::BskyError(res.json::<BskyError>().await.unwrap()));
-            }
+/// New record key, a timestamp identifier made of the current time in
+/// microseconds and a random clock id
+pub fn new_tid() -> String {
+    let micros = Utc::now().timestamp_micros() as u64;
+    let mut value = (micros << 10) | rand::random_range(0..1024u64);
 
-            let res = res.json::<PostRef>().await.unwrap();
+    let mut tid = [0u8; 13];
+    for char in tid.iter_mut().rev() {
+        *char = TID_ALPHABET[(value & 31) as usize];
+        value >>= 5;
+    }
+    String::from_utf8(tid.to_vec()).expect("TID alphabet is ASCII")
+}
 
-            Ok(res)
-        } else {
-            Err(Error::ReqwestError(res.err().unwrap()))
-        }
+/// Host without the trailing slash, so endpoint paths can be appended
+fn base_url(url: &str) -> String {
+    url.trim_end_matches('/').to_string()
+}
+
+fn xrpc_url(host: &str, method: &str) -> String {
+    format!("{}/xrpc/{}", host, method)
+}
+
+/// Decodes a successful response, or the Bsky error of a failed one.
+fn decode_response<T: DeserializeOwned>(
+    endpoint: &str,
+    status: StatusCode,
+    body: &str,
+) -> Result<T, Error> {
+    if status != StatusCode::OK {
+        return Err(match serde_json::from_str::<BskyError>(body) {
+            Ok(error) => Error::BskyError(error),
+            Err(_) => Error::HttpError(status, body.to_string()),
+        });
     }
+
+    serde_json::from_str::<T>(body).map_err(|err| Error::DecodeError(endpoint.to_string(), err))
 }
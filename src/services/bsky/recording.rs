@@ -0,0 +1,222 @@
+use super::structs::Error;
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::{Request, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Placeholder of the credentials left out of the fixtures
+const REDACTED: &str = "[redacted]";
+/// JSON fields holding credentials or personal data of the bot account
+const REDACTED_FIELDS: [&str; 5] = [
+    "password",
+    "authFactorToken",
+    "accessJwt",
+    "refreshJwt",
+    "email",
+];
+/// Request headers holding credentials
+const REDACTED_HEADERS: [&str; 2] = ["authorization", "cookie"];
+
+/// Request and response of one XRPC call, stored as one JSON file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Exchange {
+    pub endpoint: String,
+    pub recorded_at: String,
+    pub method: String,
+    pub url: String,
+    pub request_headers: BTreeMap<String, String>,
+    pub request_body: Option<Value>,
+    pub status: u16,
+    /// JSON response, or the raw text when it is not JSON
+    pub response_body: Value,
+}
+
+impl Exchange {
+    /// Responses are matched by endpoint and query, so the fixtures replay
+    /// against any host.
+    fn key(endpoint: &str, url: &str) -> String {
+        let query = url.split_once('?').map_or("", |(_, query)| query);
+        format!("{}?{}", endpoint, query)
+    }
+}
+
+/// Saves every XRPC response, with credentials redacted, to a fixture
+/// directory, one `<sequence>-<endpoint>.json` file per call.
+pub struct Recorder {
+    dir: PathBuf,
+    count: AtomicUsize,
+}
+
+impl Recorder {
+    /// Keeps numbering after the fixtures already in the directory, so
+    /// several runs recorded to it replay in order.
+    pub fn new(dir: PathBuf) -> Recorder {
+        let count = fixture_paths(&dir).map_or(0, |paths| paths.len());
+        Recorder {
+            dir,
+            count: AtomicUsize::new(count),
+        }
+    }
+
+    pub fn record(
+        &self,
+        endpoint: &str,
+        request: &Request,
+        status: StatusCode,
+        body: &str,
+    ) -> Result<(), Error> {
+        let request_headers = request
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                    String::from(REDACTED)
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+
+        let request_body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|body| redact(json_or_text(&String::from_utf8_lossy(body))));
+
+        let exchange = Exchange {
+            endpoint: endpoint.to_string(),
+            recorded_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            request_headers,
+            request_body,
+            status: status.as_u16(),
+            response_body: redact(json_or_text(body)),
+        };
+
+        let sequence = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        let path = self.dir.join(format!("{:05}-{}.json", sequence, endpoint));
+        let payload = serde_json::to_string_pretty(&exchange).expect("Exchange is serializable");
+
+        fs::create_dir_all(&self.dir).map_err(Error::IoError)?;
+        fs::write(path, payload).map_err(Error::IoError)
+    }
+}
+
+/// Serves the responses of a fixture directory written by `Recorder`.
+/// Responses to the same request come back in the recorded order, the last
+/// one is repeated once they run out.
+pub struct Replayer {
+    responses: Mutex<HashMap<String, VecDeque<Exchange>>>,
+    /// Time of the first recorded call
+    recorded_at: Option<DateTime<Utc>>,
+}
+
+impl Replayer {
+    pub fn load(dir: &Path) -> Result<Replayer, Error> {
+        let mut responses: HashMap<String, VecDeque<Exchange>> = HashMap::new();
+        let mut recorded_at: Option<DateTime<Utc>> = None;
+
+        for path in fixture_paths(dir).map_err(Error::IoError)? {
+            let text = fs::read_to_string(&path).map_err(Error::IoError)?;
+            let exchange: Exchange = serde_json::from_str(&text)
+                .map_err(|err| Error::DecodeError(path.display().to_string(), err))?;
+
+            let time = DateTime::parse_from_rfc3339(&exchange.recorded_at)
+                .map_err(|_| {
+                    Error::ReplayError(format!("invalid recorded_at of {}", path.display()))
+                })?
+                .with_timezone(&Utc);
+            recorded_at = Some(recorded_at.map_or(time, |first| first.min(time)));
+
+            responses
+                .entry(Exchange::key(&exchange.endpoint, &exchange.url))
+                .or_default()
+                .push_back(exchange);
+        }
+
+        Ok(Replayer {
+            responses: Mutex::new(responses),
+            recorded_at,
+        })
+    }
+
+    /// Time the recording started at, `None` without fixtures
+    pub fn recorded_at(&self) -> Option<DateTime<Utc>> {
+        self.recorded_at
+    }
+
+    /// Status and body recorded for the request
+    pub fn response(
+        &self,
+        endpoint: &str,
+        request: &Request,
+    ) -> Result<(StatusCode, String), Error> {
+        let key = Exchange::key(endpoint, request.url().as_str());
+        let mut responses = self.responses.lock().unwrap();
+
+        let exchange = match responses.get_mut(&key) {
+            Some(queue) if queue.len() > 1 => queue.pop_front(),
+            Some(queue) => queue.front().cloned(),
+            None => None,
+        }
+        .ok_or_else(|| Error::ReplayError(format!("no recorded response to {}", key)))?;
+
+        let status = StatusCode::from_u16(exchange.status).map_err(|_| {
+            Error::ReplayError(format!("invalid status {} of {}", exchange.status, key))
+        })?;
+        let body = match exchange.response_body {
+            Value::String(text) => text,
+            body => body.to_string(),
+        };
+
+        Ok((status, body))
+    }
+}
+
+/// Fixture files of the directory, in recorded order
+fn fixture_paths(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+fn json_or_text(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+fn redact(mut value: Value) -> Value {
+    match &mut value {
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                if REDACTED_FIELDS.contains(&name.as_str()) {
+                    *field = Value::String(String::from(REDACTED));
+                } else {
+                    *field = redact(field.take());
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                *item = redact(item.take());
+            }
+        }
+        _ => {}
+    }
+    value
+}
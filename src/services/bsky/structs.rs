@@ -1,12 +1,25 @@
-use reqwest::Error as ReqwestError;
+use reqwest::{Error as ReqwestError, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
-use std::fmt;
+use std::{fmt, io::Error as IoError};
+use tokio_tungstenite::tungstenite::Error as WebSocketError;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     ReqwestError(ReqwestError),
     BskyError(BskyError),
+    /// Response with an unexpected status and a body that is not a Bsky error
+    HttpError(StatusCode, String),
+    /// Response body that doesn't match the expected structure
+    DecodeError(String, serde_json::Error),
+    AuthError(String),
+    /// Record that is missing data the bot relies on
+    DataError(String),
+    IoError(IoError),
+    WebSocketError(WebSocketError),
+    /// Request without a recorded response in the replayed fixtures
+    ReplayError(String),
 }
 
 impl fmt::Display for Error {
@@ -18,26 +31,50 @@ impl fmt::Display for Error {
                 "Bsky error: {{ \"error\": \"{}\", \"message\": \"{}\" }}",
                 err.error, err.message
             ),
+            Error::HttpError(status, body) => write!(f, "HTTP error {}: {}", status, body),
+            Error::DecodeError(endpoint, err) => {
+                write!(f, "Unable to decode response of {}: {}", endpoint, err)
+            }
+            Error::AuthError(message) => write!(f, "Authentication error: {}", message),
+            Error::DataError(message) => write!(f, "Invalid data: {}", message),
+            Error::IoError(err) => write!(f, "IO error: {}", err),
+            Error::WebSocketError(err) => write!(f, "WebSocket error: {}", err),
+            Error::ReplayError(message) => write!(f, "Replay error: {}", message),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl Error {
+    /// The server answered and refused the request, so it had no effect. A
+    /// lost or unreadable response may belong to a request that went through.
+    pub fn is_rejection(&self) -> bool {
+        match self {
+            Error::BskyError(err) => err.error != BskyErrorCode::Unknown,
+            Error::HttpError(status, _) => status.is_client_error(),
+            Error::AuthError(_) => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct BskyError {
     pub error: BskyErrorCode,
+    #[serde(default)]
     pub message: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub enum BskyErrorCode {
     InvalidRequest,
     ExpiredToken,
     InvalidToken,
     AccountTakedown,
     AuthFactorTokenRequired,
+    RecordNotFound,
     #[serde(untagged)]
     Unknown,
 }
@@ -50,6 +87,7 @@ impl fmt::Display for BskyErrorCode {
             BskyErrorCode::InvalidToken => write!(f, "InvalidToken"),
             BskyErrorCode::AccountTakedown => write!(f, "AccountTakedown"),
             BskyErrorCode::AuthFactorTokenRequired => write!(f, "AuthFactorTokenRequired"),
+            BskyErrorCode::RecordNotFound => write!(f, "RecordNotFound"),
             BskyErrorCode::Unknown => write!(f, "UnhandledException"),
         }
     }
@@ -69,9 +107,34 @@ pub struct AuthenticationResponse {
     pub access_jwt: String,
     #[serde(rename = "refreshJwt")]
     pub refresh_jwt: String,
-    pub handle: String,
     pub did: String,
     pub status: Option<AccountStatus>,
+    #[serde(rename = "didDoc")]
+    pub did_doc: Option<DidDocument>,
+}
+
+#[derive(Deserialize)]
+pub struct DidDocument {
+    #[serde(default)]
+    pub service: Vec<DidService>,
+}
+
+#[derive(Deserialize)]
+pub struct DidService {
+    pub id: String,
+    /// A URL string for the PDS, DID documents allow maps and lists as well
+    #[serde(rename = "serviceEndpoint")]
+    pub service_endpoint: serde_json::Value,
+}
+
+impl DidDocument {
+    /// Endpoint of the PDS hosting the account
+    pub fn pds_endpoint(&self) -> Option<&str> {
+        self.service
+            .iter()
+            .filter(|service| service.id.ends_with("#atproto_pds"))
+            .find_map(|service| service.service_endpoint.as_str())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -104,6 +167,16 @@ pub struct Author {
     pub handle: Option<String>,
     #[serde(rename = "displayName")]
     pub display_name: Option<String>,
+    /// Only present in detailed profiles returned by `getProfiles`
+    #[serde(rename = "followersCount")]
+    pub followers_count: Option<i32>,
+    #[serde(rename = "postsCount")]
+    pub posts_count: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct ProfilesResponse {
+    pub profiles: Vec<Author>,
 }
 
 #[derive(Serialize)]
@@ -141,6 +214,11 @@ pub struct FeedListOptions {
     pub filter: Option<FeedFilter>,
 }
 
+#[derive(Deserialize)]
+pub struct PostsResponse {
+    pub posts: Vec<Post>,
+}
+
 #[derive(Deserialize)]
 pub struct FeedResponse {
     pub feed: Vec<Feed>,
@@ -166,6 +244,7 @@ pub struct Reason {
 #[derive(PartialEq)]
 pub enum ReasonType {
     Repost,
+    Pin,
     Unknown(String),
 }
 
@@ -177,6 +256,7 @@ impl<'de> Deserialize<'de> for ReasonType {
         let s = String::deserialize(deserializer)?;
         match s.as_str() {
             "app.bsky.feed.defs#reasonRepost" => Ok(ReasonType::Repost),
+            "app.bsky.feed.defs#reasonPin" => Ok(ReasonType::Pin),
             other => Ok(ReasonType::Unknown(other.to_string())),
         }
     }
@@ -208,21 +288,25 @@ pub struct Record {
     // pub text: String,
     #[serde(rename = "createdAt")]
     pub created_at: String,
+    pub reply: Option<Reply>,
     // pub langs: Option<Vec<String>>,
     // pub tags: Option<Vec<String>>,
 }
 
+#[skip_serializing_none]
 #[derive(Serialize)]
 pub struct NewPost {
     pub repo: String,
     pub collection: RecordType,
+    /// Record key chosen by the client, generated by the PDS when missing
+    pub rkey: Option<String>,
     pub record: NewRecord,
     pub lang: Vec<String>,
     pub validate: bool,
 }
 
 #[skip_serializing_none]
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct NewRecord {
     #[serde(rename = "$type")]
     pub record_type: RecordType,
@@ -234,7 +318,7 @@ pub struct NewRecord {
     pub embed: Option<Embed>,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Reply {
     pub root: PostRef,
     pub parent: PostRef,
@@ -246,20 +330,20 @@ pub struct PostRef {
     pub cid: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub enum RecordType {
     #[serde(rename = "app.bsky.feed.post")]
     Post,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Facet {
     pub index: Index,
     pub features: Vec<Feature>,
 }
 
 #[skip_serializing_none]
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Feature {
     #[serde(rename = "$type")]
     pub feature_type: FeatureType,
@@ -268,7 +352,7 @@ pub struct Feature {
     pub tag: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Index {
     #[serde(rename = "byteStart")]
     pub byte_start: u64,
@@ -276,7 +360,7 @@ pub struct Index {
     pub byte_end: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub enum FeatureType {
     #[serde(rename = "app.bsky.richtext.facet#mention")]
     Mention,
@@ -286,14 +370,14 @@ pub enum FeatureType {
     Tag,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Embed {
     #[serde(rename = "$type")]
     pub embed_type: EmbedType,
     pub record: PostRef,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub enum EmbedType {
     #[serde(rename = "app.bsky.embed.record")]
     Record,
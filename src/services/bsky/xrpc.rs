@@ -0,0 +1,308 @@
+use super::structs::Error;
+use chrono::Utc;
+use reqwest::{header::HeaderMap, RequestBuilder, Response, StatusCode};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Requests kept in reserve before waiting for the rate limit window to reset
+const RATE_LIMIT_RESERVE: u64 = 5;
+/// Longest wait for a rate limit window, a later reset is treated as a bogus
+/// header
+const MAX_RATE_LIMIT_WAIT_SECS: i64 = 3600;
+
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Retry only failures that guarantee the server didn't process the request
+    pub unsent_only: bool,
+}
+
+impl RetryPolicy {
+    /// Reads are idempotent, so every transient failure is retried
+    pub const READ: RetryPolicy = RetryPolicy {
+        max_attempts: 5,
+        base_delay: Duration::from_millis(500),
+        max_delay: Duration::from_secs(30),
+        unsent_only: false,
+    };
+
+    /// Writes are retried only on connection failures and rate limiting, a
+    /// timeout or 5xx could mean the record was already created
+    pub const WRITE: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_secs(1),
+        max_delay: Duration::from_secs(60),
+        unsent_only: true,
+    };
+
+    /// Full jitter exponential backoff
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+
+        Duration::from_millis(rand::random_range(0..=exponential.as_millis() as u64))
+    }
+}
+
+struct RateLimit {
+    remaining: u64,
+    reset: i64,
+}
+
+/// Rate limit budgets reported by each host in `ratelimit-*` headers, plus
+/// our own max request rate shared by every request.
+#[derive(Default)]
+pub struct RateLimits {
+    hosts: Mutex<HashMap<String, RateLimit>>,
+    interval: Option<Duration>,
+    next_request: Mutex<Option<Instant>>,
+}
+
+impl RateLimits {
+    /// Spaces requests evenly to stay under `requests_per_second`, a zero
+    /// rate leaves only the limits reported by the hosts.
+    pub fn with_max_rate(requests_per_second: f64) -> Self {
+        RateLimits {
+            interval: (requests_per_second > 0.0)
+                .then(|| Duration::from_secs_f64(1.0 / requests_per_second)),
+            ..Default::default()
+        }
+    }
+
+    /// Reserves the next free slot of the max request rate and returns the
+    /// time to wait for it.
+    fn throttle(&self) -> Option<Duration> {
+        let interval = self.interval?;
+        let mut next_request = self.next_request.lock().unwrap();
+        let now = Instant::now();
+
+        let slot = next_request.map_or(now, |next| next.max(now));
+        *next_request = Some(slot + interval);
+
+        (slot > now).then(|| slot - now)
+    }
+
+    /// Time to wait before the next request to the host, taking one request
+    /// out of the known budget.
+    fn acquire(&self, host: &str) -> Option<Duration> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let limit = hosts.get_mut(host)?;
+        let now = Utc::now().timestamp();
+
+        if limit.reset <= now {
+            hosts.remove(host);
+            return None;
+        }
+
+        if limit.remaining > RATE_LIMIT_RESERVE {
+            limit.remaining -= 1;
+            return None;
+        }
+
+        Some(Duration::from_secs((limit.reset - now) as u64))
+    }
+
+    fn update(&self, host: &str, headers: &HeaderMap) {
+        let remaining = header_number(headers, "ratelimit-remaining");
+        let reset = header_number(headers, "ratelimit-reset");
+
+        if let (Some(remaining), Some(reset)) = (remaining, reset) {
+            let latest_reset = Utc::now().timestamp() + MAX_RATE_LIMIT_WAIT_SECS;
+            self.hosts.lock().unwrap().insert(
+                host.to_string(),
+                RateLimit {
+                    remaining: remaining.max(0) as u64,
+                    reset: reset.min(latest_reset),
+                },
+            );
+        }
+    }
+
+    /// Delay until the rate limit window of the host resets, if it is known.
+    fn until_reset(&self, host: &str) -> Option<Duration> {
+        let hosts = self.hosts.lock().unwrap();
+        let limit = hosts.get(host)?;
+        let seconds = limit.reset - Utc::now().timestamp();
+
+        (seconds > 0).then(|| Duration::from_secs(seconds as u64))
+    }
+}
+
+/// Sends the request built by `request`, waiting for the host's rate limit
+/// budget and retrying transient failures according to the policy.
+pub async fn send<F>(
+    rate_limits: &RateLimits,
+    endpoint: &str,
+    policy: &RetryPolicy,
+    request: F,
+) -> Result<Response, Error>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let (client, request) = request().build_split();
+        let request = request.map_err(Error::ReqwestError)?;
+        let host = request.url().host_str().unwrap_or_default().to_string();
+
+        if let Some(delay) = rate_limits.throttle() {
+            tokio::time::sleep(delay).await;
+        }
+
+        if let Some(delay) = rate_limits.acquire(&host) {
+            println!(
+                "Rate limit of {} is almost exhausted, waiting {}s",
+                host,
+                delay.as_secs()
+            );
+            tokio::time::sleep(delay).await;
+        }
+
+        let res = client.execute(request).await;
+
+        let retry_reason = match &res {
+            Ok(res) => {
+                rate_limits.update(&host, res.headers());
+
+                let status = res.status();
+                let is_transient = status == StatusCode::TOO_MANY_REQUESTS
+                    || (status.is_server_error() && !policy.unsent_only);
+
+                is_transient.then(|| format!("status {}", status))
+            }
+            Err(err) if err.is_connect() => Some(err.to_string()),
+            Err(err) if !policy.unsent_only && (err.is_timeout() || err.is_request()) => {
+                Some(err.to_string())
+            }
+            Err(_) => None,
+        };
+
+        let Some(retry_reason) = retry_reason else {
+            return res.map_err(Error::ReqwestError);
+        };
+
+        if attempt >= policy.max_attempts {
+            return res.map_err(Error::ReqwestError);
+        }
+
+        let rate_limited = matches!(&res, Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS);
+        let delay = if rate_limited {
+            rate_limits
+                .until_reset(&host)
+                .unwrap_or_else(|| policy.backoff(attempt))
+        } else {
+            policy.backoff(attempt)
+        };
+
+        println!(
+            "Retrying {} in {}ms (attempt {} of {}): {}",
+            endpoint,
+            delay.as_millis(),
+            attempt + 1,
+            policy.max_attempts,
+            retry_reason
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn header_number(headers: &HeaderMap, name: &str) -> Option<i64> {
+    headers.get(name)?.to_str().ok()?.trim().parse::<i64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn backoff_stays_under_the_exponential_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            unsent_only: false,
+        };
+
+        for _ in 0..100 {
+            assert!(policy.backoff(1) <= Duration::from_millis(100));
+            assert!(policy.backoff(3) <= Duration::from_millis(400));
+            assert!(policy.backoff(5) <= Duration::from_secs(1));
+            // Huge attempt numbers saturate instead of overflowing
+            assert!(policy.backoff(u32::MAX) <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn header_numbers_are_parsed() {
+        let headers = headers(&[("ratelimit-remaining", " 42 "), ("ratelimit-reset", "soon")]);
+
+        assert_eq!(header_number(&headers, "ratelimit-remaining"), Some(42));
+        assert_eq!(header_number(&headers, "ratelimit-reset"), None);
+        assert_eq!(header_number(&headers, "ratelimit-limit"), None);
+    }
+
+    #[test]
+    fn budget_is_taken_from_the_reported_limit() {
+        let rate_limits = RateLimits::default();
+        let reset = Utc::now().timestamp() + 60;
+        rate_limits.update(
+            "pds",
+            &headers(&[
+                ("ratelimit-remaining", "7"),
+                ("ratelimit-reset", &reset.to_string()),
+            ]),
+        );
+
+        assert_eq!(rate_limits.acquire("pds"), None);
+        assert_eq!(rate_limits.acquire("pds"), None);
+        // The reserve is left for other requests
+        assert!(rate_limits.acquire("pds").is_some());
+        assert_eq!(rate_limits.acquire("appview"), None);
+    }
+
+    #[test]
+    fn update_needs_both_headers() {
+        let rate_limits = RateLimits::default();
+        rate_limits.update("pds", &headers(&[("ratelimit-remaining", "0")]));
+
+        assert_eq!(rate_limits.acquire("pds"), None);
+        assert_eq!(rate_limits.until_reset("pds"), None);
+    }
+
+    #[test]
+    fn bogus_limits_are_clamped() {
+        let rate_limits = RateLimits::default();
+        let far_future = Utc::now().timestamp() + 365 * 24 * 3600;
+        rate_limits.update(
+            "pds",
+            &headers(&[
+                ("ratelimit-remaining", "-3"),
+                ("ratelimit-reset", &far_future.to_string()),
+            ]),
+        );
+
+        let max_wait = Duration::from_secs(MAX_RATE_LIMIT_WAIT_SECS as u64);
+        // A negative budget is exhausted, not a huge one
+        let wait = rate_limits.acquire("pds").unwrap();
+        assert!(wait <= max_wait);
+        assert!(rate_limits.until_reset("pds").unwrap() <= max_wait);
+    }
+}
@@ -1,30 +1,130 @@
-use chrono::{DateTime, Duration, Utc};
-use futures::future::join_all;
-use std::vec;
-use tokio::task;
-
-const START_TIME: i64 = 24;
-const END_TIME: i64 = 25;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use diesel::result::Error as DieselError;
+use futures::{stream, StreamExt};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::Path,
+    sync::{LazyLock, Mutex},
+    time::Instant,
+};
 
 use crate::{
+    config::{
+        CollectionMode, Config, ConfigError, PeriodRankingConfig, MONTHLY_PERIOD_DAYS,
+        WEEKLY_PERIOD_DAYS,
+    },
     database::{
-        models::{NewPost, NewUser, User},
-        queries,
+        models::{
+            NewPost, NewPostMetricsSnapshot, NewPublication, NewRanking, NewRankingEntry, NewUser,
+            Post, PostCounter, PostMetrics, Publication, PublicationStatus, RankingKind, User,
+            UserAudience,
+        },
+        queries::{self, AuthorStats},
+    },
+    ranking::{
+        authors::rank_authors,
+        diversity::select_diverse,
+        score_candidates, score_candidates_at,
+        scoring::{self, Counters, Weights},
+        trending, Candidate,
     },
     services::bsky::{
         facets::parse_facets_with_users,
+        jetstream::{self, Engagement, Event, Subscription},
+        new_tid,
         structs::{
-            Embed, EmbedType, FeedFilter, FeedListOptions, FollowersListOptions, PostRef,
-            ReasonType,
+            Embed, EmbedType, Error as BskyError, Feed, FeedFilter, FeedListOptions,
+            FollowersListOptions, Post as BskyPost, PostRef, ReasonType, Reply,
         },
-        Bsky,
+        Bsky, GET_POSTS_LIMIT, GET_PROFILES_LIMIT, MAX_POST_GRAPHEMES,
     },
-    utils::bench::Bench,
+    utils::{bench::Bench, clock, template},
 };
 
-pub async fn sync_users(bsky: &Bsky) {
+/// An in-flight entry untouched for longer is considered abandoned by the run
+/// that claimed it
+const PUBLICATION_LEASE_MINUTES: i64 = 10;
+
+/// Kinds of the threads being posted by this process
+static PUBLISHING: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+/// Marks the kind of thread as being posted until dropped
+struct PublishGuard(String);
+
+impl PublishGuard {
+    /// `None` when a thread of the kind is already being posted, by the
+    /// scheduler resuming it or by a cron job
+    fn acquire(kind: &str) -> Option<PublishGuard> {
+        if !PUBLISHING.lock().unwrap().insert(kind.to_string()) {
+            println!("A {} thread is already being posted", kind);
+            return None;
+        }
+        Some(PublishGuard(kind.to_string()))
+    }
+}
+
+impl Drop for PublishGuard {
+    fn drop(&mut self) {
+        PUBLISHING.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// A tracked post missing from that many refreshes in a row is considered
+/// deleted, a single miss can be a hiccup of the AppView
+const MAX_MISSED_REFRESHES: i32 = 3;
+
+/// Name of the stored cursor of the Jetstream subscription
+const FIREHOSE_CURSOR_NAME: &str = "jetstream";
+const FIREHOSE_CURSOR_SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// Users are synced daily, newly followed ones are picked up within the hour
+const FIREHOSE_USERS_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+/// Publication kind of the authors leaderboard, it is not a ranking of posts
+pub const LEADERBOARD_KIND: &str = "authors";
+
+#[derive(Debug)]
+pub enum Error {
+    Bsky(BskyError),
+    Database(DieselError),
+    Config(ConfigError),
+    /// Publication entry claimed by another run
+    Claimed(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Bsky(err) => write!(f, "{}", err),
+            Error::Database(err) => write!(f, "Database error: {}", err),
+            Error::Config(err) => write!(f, "{}", err),
+            Error::Claimed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<BskyError> for Error {
+    fn from(err: BskyError) -> Self {
+        Error::Bsky(err)
+    }
+}
+
+impl From<DieselError> for Error {
+    fn from(err: DieselError) -> Self {
+        Error::Database(err)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(err: ConfigError) -> Self {
+        Error::Config(err)
+    }
+}
+
+pub async fn sync_users(bsky: &Bsky, config: &Config) -> Result<(), Error> {
     let mut options = FollowersListOptions {
-        actor: String::from("bsky.one"),
+        actor: config.provider.account.clone(),
         limit: 100,
         cursor: None,
     };
@@ -32,15 +132,21 @@ pub async fn sync_users(bsky: &Bsky) {
     let mut new_users: Vec<NewUser> = Vec::new();
     let bench = Bench::start("Collecting users from Bsky");
     loop {
-        let res = bsky.get_actor_followers(&options).await.unwrap();
+        let res = bsky.get_actor_followers(&options).await?;
 
         let mut follows: Vec<NewUser> = res
             .follows
             .iter()
-            .map(|v| NewUser {
-                did: v.did.clone(),
-                handle: v.handle.clone().unwrap(),
-                display_name: v.display_name.clone(),
+            .filter_map(|v| match &v.handle {
+                Some(handle) => Some(NewUser {
+                    did: v.did.clone(),
+                    handle: handle.clone(),
+                    display_name: v.display_name.clone(),
+                }),
+                None => {
+                    println!("Skipping user {} without handle", v.did);
+                    None
+                }
             })
             .collect();
 
@@ -54,76 +160,496 @@ pub async fn sync_users(bsky: &Bsky) {
     bench.end();
 
     let bench = Bench::start(format!("Syncing {} users with database", new_users.len()).as_str());
-    if let Err(error) = queries::sync_users(&new_users).await {
-        println!("Error during sync with database: {}", error);
+    queries::sync_users(&new_users).await?;
+    bench.end();
+
+    let dids: Vec<String> = new_users.into_iter().map(|user| user.did).collect();
+    sync_users_audience(bsky, config, &dids).await?;
+
+    Ok(())
+}
+
+/// Stores follower and post counts of the users from their profiles.
+async fn sync_users_audience(bsky: &Bsky, config: &Config, dids: &[String]) -> Result<(), Error> {
+    let bench = Bench::start(format!("Collecting profiles of {} users", dids.len()).as_str());
+
+    let batches: Vec<Vec<String>> = dids
+        .chunks(GET_PROFILES_LIMIT)
+        .map(|batch| batch.to_vec())
+        .collect();
+
+    let mut batches = stream::iter(batches)
+        .map(|batch| async move {
+            let res = bsky.get_profiles(&batch).await;
+            (batch, res)
+        })
+        .buffer_unordered(config.collection.concurrency);
+
+    let mut audiences: Vec<(String, UserAudience)> = Vec::new();
+    while let Some((batch, res)) = batches.next().await {
+        match res {
+            Ok(profiles) => audiences.extend(profiles.into_iter().map(|profile| {
+                let audience = UserAudience {
+                    followers_count: profile.followers_count,
+                    posts_count: profile.posts_count,
+                };
+                (profile.did, audience)
+            })),
+            Err(error) => println!("Skipping profiles of {} users: {}", batch.len(), error),
+        }
     }
+
+    queries::update_users_audience(&audiences)?;
     bench.end();
+
+    Ok(())
 }
 
-pub async fn sync_users_posts(bsky: &Bsky) {
-    let limit: i64 = 100;
-    let mut cursor: i32 = 0;
+/// Collects posts the way the configured collection mode does it.
+pub async fn collect_posts(bsky: &Bsky, config: &Config) -> Result<(), Error> {
+    println!("Collecting posts in {} mode", config.collection.mode);
 
-    let mut collected_posts: Vec<NewPost> = Vec::new();
-    let total_users = queries::get_total_users_count().unwrap();
-    let mut processed_users_count: f32 = 0.0;
+    match config.collection.mode {
+        CollectionMode::Feed => sync_users_posts(bsky, config).await,
+        CollectionMode::Refresh => {
+            discover_users_posts(bsky, config).await?;
+            refresh_posts_metrics(bsky, config).await
+        }
+    }
+}
 
-    let start_time = Utc::now() - Duration::hours(START_TIME);
+/// Samples the posts that entered the collection window since the last run.
+pub async fn sync_users_posts(bsky: &Bsky, config: &Config) -> Result<(), Error> {
+    let start_time = clock::now() - Duration::hours(config.collection.window_start_hours);
+    let end_time = clock::now() - Duration::hours(config.collection.window_end_hours);
+
+    let collected_posts = crawl_authors_feeds(bsky, config, &start_time, &end_time).await?;
+
+    let bench =
+        Bench::start(format!("Syncing {} posts with database", collected_posts.len()).as_str());
+    queries::save_posts(&collected_posts)?;
+    bench.end();
+
+    Ok(())
+}
+
+/// Starts tracking the posts published since the previous collection, their
+/// counters are kept up to date by `refresh_posts_metrics`.
+pub async fn discover_users_posts(bsky: &Bsky, config: &Config) -> Result<(), Error> {
+    let start_time = clock::now();
+    let end_time = start_time - Duration::hours(config.collection.discovery_hours);
+
+    let collected_posts = crawl_authors_feeds(bsky, config, &start_time, &end_time).await?;
+
+    let uris: Vec<String> = collected_posts
+        .iter()
+        .map(|post| post.uri.clone())
+        .collect();
+    let known_uris = queries::get_known_post_uris(&uris)?;
+    let new_posts: Vec<NewPost> = collected_posts
+        .into_iter()
+        .filter(|post| !known_uris.contains(&post.uri))
+        .collect();
+
+    let bench = Bench::start(format!("Tracking {} new posts", new_posts.len()).as_str());
+    queries::save_posts(&new_posts)?;
+    bench.end();
+
+    Ok(())
+}
+
+/// Updates the counters of the tracked posts still inside the collection
+/// window. Posts missing from `getPosts` are left out of the rankings, and
+/// dropped as deleted once they are missed `MAX_MISSED_REFRESHES` times in a
+/// row.
+pub async fn refresh_posts_metrics(bsky: &Bsky, config: &Config) -> Result<(), Error> {
+    let since = (clock::now() - Duration::hours(config.collection.window_end_hours)).naive_utc();
+    let tracked_posts = queries::get_tracked_posts(since)?;
+
+    let bench =
+        Bench::start(format!("Refreshing counters of {} posts", tracked_posts.len()).as_str());
+
+    let batches: Vec<Vec<Post>> = tracked_posts
+        .chunks(GET_POSTS_LIMIT)
+        .map(|batch| batch.to_vec())
+        .collect();
+
+    let mut batches = stream::iter(batches)
+        .map(|batch| async move {
+            let uris: Vec<String> = batch.iter().map(|post| post.uri.clone()).collect();
+            (batch, bsky.get_posts(&uris).await)
+        })
+        .buffer_unordered(config.collection.concurrency);
+
+    let mut metrics: Vec<(i32, PostMetrics)> = Vec::new();
+    let mut snapshots: Vec<NewPostMetricsSnapshot> = Vec::new();
+    let mut missing_post_ids: Vec<i32> = Vec::new();
+
+    // Trending compares how counters of young posts grow between refreshes
+    let snapshot_since = config
+        .trending
+        .enabled
+        .then(|| (clock::now() - Duration::hours(config.trending.max_age_hours)).naive_utc());
+
+    while let Some((batch, res)) = batches.next().await {
+        let views = match res {
+            Ok(views) => views,
+            Err(error) => {
+                println!("Skipping refresh of {} posts: {}", batch.len(), error);
+                continue;
+            }
+        };
+
+        let views: HashMap<&str, &BskyPost> =
+            views.iter().map(|view| (view.uri.as_str(), view)).collect();
+
+        for post in &batch {
+            let Some(view) = views.get(post.uri.as_str()) else {
+                missing_post_ids.push(post.id);
+                continue;
+            };
+
+            let post_metrics = post_metrics(config, view);
+            if snapshot_since.is_some_and(|since| post.created_at >= since) {
+                snapshots.push(NewPostMetricsSnapshot {
+                    post_id: post.id,
+                    reply_count: post_metrics.reply_count,
+                    repost_count: post_metrics.repost_count,
+                    like_count: post_metrics.like_count,
+                    quote_count: post_metrics.quote_count,
+                    total_points: post_metrics.total_points,
+                });
+            }
+            metrics.push((post.id, post_metrics));
+        }
+    }
+
+    queries::update_post_metrics(&metrics)?;
+    queries::save_metrics_snapshots(&snapshots)?;
+    let deleted_post_ids = queries::mark_posts_missing(&missing_post_ids, MAX_MISSED_REFRESHES)?;
+    queries::delete_posts(&deleted_post_ids)?;
+
+    bench.end_with(
+        format!(
+            "Refreshed {} posts, {} missing, dropped {} deleted posts",
+            metrics.len(),
+            missing_post_ids.len(),
+            deleted_post_ids.len()
+        )
+        .as_str(),
+    );
+
+    Ok(())
+}
+
+/// Counts engagement with tracked posts from the Jetstream firehose until the
+/// process stops, reconnecting from the stored cursor after failures.
+pub async fn run_firehose(config: &Config) -> Result<(), Error> {
+    let mut counter = FirehoseCounter::new(queries::get_firehose_cursor(FIREHOSE_CURSOR_NAME)?)?;
+    let mut failed_attempts: u32 = 0;
 
-    let bench = Bench::start("Collecting posts from Bsky");
     loop {
-        let users_bench = Bench::start_silent();
+        // Jetstream replays the event at the cursor itself, it was already counted
+        let cursor = counter.cursor.map(|cursor| cursor + 1);
 
-        let users = queries::get_users_list(limit, cursor).unwrap();
+        match Subscription::connect(&config.firehose.url, cursor).await {
+            Ok(mut subscription) => {
+                println!("Connected to firehose {}", config.firehose.url);
+                failed_attempts = 0;
 
-        if users.is_empty() {
-            break;
+                while let Some(event) = subscription.next_event().await {
+                    match event {
+                        // Reconnecting resumes from the last counted event,
+                        // so this one is counted again
+                        Ok(event) => {
+                            if let Err(error) = counter.handle_event(config, &event) {
+                                println!("Unable to count engagement of event: {}", error);
+                                break;
+                            }
+                        }
+                        Err(BskyError::DecodeError(_, _)) => counter.skipped_events += 1,
+                        Err(error) => {
+                            println!("Firehose connection lost: {}", error);
+                            break;
+                        }
+                    }
+
+                    if counter.cursor_saved_at.elapsed() >= FIREHOSE_CURSOR_SAVE_INTERVAL {
+                        counter.save_cursor()?;
+                    }
+                }
+
+                counter.save_cursor()?;
+            }
+            Err(error) => println!("Unable to connect to firehose: {}", error),
         }
 
-        processed_users_count += users.len() as f32;
-        if let Some(last_user) = users.last() {
-            cursor = last_user.id;
+        failed_attempts += 1;
+        let delay = std::time::Duration::from_secs(2u64.pow(failed_attempts.min(6)));
+        println!("Reconnecting to firehose in {}s", delay.as_secs());
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Counts a recorded Jetstream stream (one event per line) like the live
+/// subscription does, storing the cursor with the counters. Events up to the
+/// stored cursor were counted already and are skipped.
+pub fn apply_firehose(config: &Config, path: &Path) -> Result<(), Error> {
+    let bench = Bench::start(format!("Counting firehose events from {}", path.display()).as_str());
+
+    let events = fs::read_to_string(path).map_err(BskyError::IoError)?;
+
+    let stored_cursor = queries::get_firehose_cursor(FIREHOSE_CURSOR_NAME)?;
+    let mut counter = FirehoseCounter::new(stored_cursor)?;
+    for line in events.lines().filter(|line| !line.trim().is_empty()) {
+        match jetstream::parse_event(line) {
+            Ok(event) if stored_cursor.is_some_and(|cursor| event.time_us <= cursor) => {}
+            Ok(event) => counter.handle_event(config, &event)?,
+            Err(_) => counter.skipped_events += 1,
         }
+    }
+    if counter.cursor != stored_cursor {
+        counter.save_cursor()?;
+    }
 
-        let futures = users.iter().map(|user| {
-            let user = user.clone();
-            let bsky = bsky.clone();
-            task::spawn(async move { sync_latest_author_posts(&bsky, &user, &start_time).await })
-        });
+    bench.end_with(counter.summary().as_str());
+
+    Ok(())
+}
+
+/// Reports the engagement a recorded Jetstream stream (one event per line)
+/// would add to the tracked posts. Nothing is written, neither the counters
+/// nor the stored cursor of the live subscription.
+pub fn replay_firehose(config: &Config, path: &Path) -> Result<(), Error> {
+    let bench = Bench::start(format!("Replaying firehose events from {}", path.display()).as_str());
+
+    let events = fs::read_to_string(path).map_err(BskyError::IoError)?;
+
+    let mut counter = FirehoseCounter::new(None)?;
+    let mut engagements: Vec<(String, PostCounter, i32)> = Vec::new();
+    for line in events.lines().filter(|line| !line.trim().is_empty()) {
+        match jetstream::parse_event(line) {
+            Ok(event) => {
+                engagements.extend(counter.engagements(config, &event).into_iter().map(
+                    |(post_uri, engagement, points)| (post_uri.to_string(), engagement, points),
+                ))
+            }
+            Err(_) => counter.skipped_events += 1,
+        }
+    }
+
+    let uris: Vec<String> = engagements
+        .iter()
+        .map(|(post_uri, _, _)| post_uri.clone())
+        .collect();
+    let tracked_uris = queries::get_tracked_post_uris(&uris)?;
+
+    let mut totals = Counters::default();
+    let mut points = 0;
+    for (post_uri, engagement, engagement_points) in &engagements {
+        if !tracked_uris.contains(post_uri) {
+            continue;
+        }
 
-        let results = join_all(futures).await;
+        counter.counted_engagements += 1;
+        points += engagement_points;
+        match engagement {
+            PostCounter::Like => totals.likes += 1,
+            PostCounter::Repost => totals.reposts += 1,
+            PostCounter::Reply => totals.replies += 1,
+            PostCounter::Quote => totals.quotes += 1,
+        }
+    }
+
+    bench.end_with(
+        format!(
+            "Would count {} engagements with {} tracked posts ({} likes, {} reposts, {} replies, {} quotes, {} points), skipped {} undecodable events",
+            counter.counted_engagements,
+            tracked_uris.len(),
+            totals.likes,
+            totals.reposts,
+            totals.replies,
+            totals.quotes,
+            points,
+            counter.skipped_events
+        )
+        .as_str(),
+    );
 
-        let mut posts: Vec<NewPost> = results
+    Ok(())
+}
+
+struct FirehoseCounter {
+    /// DIDs of the synced users, engagement with anyone else is ignored early
+    dids: HashSet<String>,
+    dids_loaded_at: Instant,
+    cursor: Option<i64>,
+    cursor_saved_at: Instant,
+    counted_engagements: usize,
+    skipped_events: usize,
+}
+
+impl FirehoseCounter {
+    fn new(cursor: Option<i64>) -> Result<FirehoseCounter, DieselError> {
+        Ok(FirehoseCounter {
+            dids: load_users_dids()?,
+            dids_loaded_at: Instant::now(),
+            cursor,
+            cursor_saved_at: Instant::now(),
+            counted_engagements: 0,
+            skipped_events: 0,
+        })
+    }
+
+    /// Engagements of the event with posts of the synced users, with the
+    /// counter they bump and the points they are worth
+    fn engagements<'a>(
+        &mut self,
+        config: &Config,
+        event: &'a Event,
+    ) -> Vec<(&'a str, PostCounter, i32)> {
+        if self.dids_loaded_at.elapsed() >= FIREHOSE_USERS_RELOAD_INTERVAL {
+            match load_users_dids() {
+                Ok(dids) => self.dids = dids,
+                Err(error) => println!("Unable to reload users for firehose: {}", error),
+            }
+            self.dids_loaded_at = Instant::now();
+        }
+
+        event
+            .engagements()
             .into_iter()
-            .filter_map(|r| r.ok())
-            .flat_map(|posts| posts)
-            .collect();
+            .filter(|(_, post_uri)| {
+                jetstream::post_author(post_uri).is_some_and(|author| self.dids.contains(author))
+            })
+            .map(|(engagement, post_uri)| match engagement {
+                Engagement::Like => (post_uri, PostCounter::Like, config.scoring.like_weight),
+                Engagement::Repost => (post_uri, PostCounter::Repost, config.scoring.repost_weight),
+                Engagement::Reply => (post_uri, PostCounter::Reply, config.scoring.reply_weight),
+                Engagement::Quote => (post_uri, PostCounter::Quote, config.scoring.quote_weight),
+            })
+            .collect()
+    }
+
+    /// Counts the engagements of the event. The cursor is stored together
+    /// with the counters, events without engagement only move it in memory.
+    /// It stays before an event that failed to count.
+    fn handle_event(&mut self, config: &Config, event: &Event) -> Result<(), DieselError> {
+        let engagements = self.engagements(config, event);
 
-        collected_posts.append(&mut posts);
+        if !engagements.is_empty() {
+            self.counted_engagements +=
+                queries::count_engagements(&engagements, FIREHOSE_CURSOR_NAME, event.time_us)?;
+            self.cursor_saved_at = Instant::now();
+        }
 
-        let percentage = processed_users_count / total_users as f32 * 100.0;
-        let log_message = format!(
-            "Synced {} users of {} ({:.2}%)",
-            processed_users_count, total_users, percentage
-        );
+        self.cursor = Some(event.time_us);
 
-        users_bench.end_with(log_message.as_str());
+        Ok(())
     }
-    bench.end();
 
-    let bench =
-        Bench::start(format!("Syncing {} posts with database", collected_posts.len()).as_str());
-    if let Err(error) = queries::save_posts(&collected_posts) {
-        println!("Error during sync with database: {}", error);
+    fn save_cursor(&mut self) -> Result<(), DieselError> {
+        if let Some(cursor) = self.cursor {
+            queries::save_firehose_cursor(FIREHOSE_CURSOR_NAME, cursor)?;
+            println!("Firehose: {}", self.summary());
+        }
+        self.cursor_saved_at = Instant::now();
+
+        Ok(())
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "counted {} engagements, skipped {} undecodable events",
+            self.counted_engagements, self.skipped_events
+        )
+    }
+}
+
+fn load_users_dids() -> Result<HashSet<String>, DieselError> {
+    Ok(get_all_users()?.into_iter().map(|user| user.did).collect())
+}
+
+/// Crawls the feeds of all users for posts created between `end_time` and
+/// `start_time`.
+async fn crawl_authors_feeds(
+    bsky: &Bsky,
+    config: &Config,
+    start_time: &DateTime<Utc>,
+    end_time: &DateTime<Utc>,
+) -> Result<Vec<NewPost>, Error> {
+    let users = get_all_users()?;
+    let total_users = users.len();
+
+    let bench = Bench::start("Collecting posts from Bsky");
+
+    // One shared pool of crawls, a new author starts as soon as any other is done
+    let mut crawls = stream::iter(users)
+        .map(|user| async move {
+            let bench = Bench::start_silent();
+            let res = sync_latest_author_posts(bsky, config, &user, start_time, end_time).await;
+            (user, res, bench)
+        })
+        .buffer_unordered(config.collection.concurrency);
+
+    let mut collected_posts: Vec<NewPost> = Vec::new();
+    let mut processed_users_count = 0;
+
+    while let Some((user, res, author_bench)) = crawls.next().await {
+        processed_users_count += 1;
+        let percentage = processed_users_count as f32 / total_users as f32 * 100.0;
+
+        match res {
+            Ok(mut posts) => {
+                let log_message = format!(
+                    "Synced {} posts of {} ({} of {}, {:.2}%)",
+                    posts.len(),
+                    user.handle,
+                    processed_users_count,
+                    total_users,
+                    percentage
+                );
+                author_bench.end_with(log_message.as_str());
+
+                collected_posts.append(&mut posts);
+            }
+            Err(error) => println!(
+                "Skipping posts of {} ({} of {}, {:.2}%): {}",
+                user.handle, processed_users_count, total_users, percentage, error
+            ),
+        }
     }
     bench.end();
+
+    Ok(collected_posts)
+}
+
+fn get_all_users() -> Result<Vec<User>, DieselError> {
+    let limit: i64 = 1000;
+    let mut cursor: i32 = 0;
+    let mut users: Vec<User> = Vec::new();
+
+    loop {
+        let mut page = queries::get_users_list(limit, cursor)?;
+
+        match page.last() {
+            Some(last_user) => cursor = last_user.id,
+            None => break,
+        }
+
+        users.append(&mut page);
+    }
+
+    Ok(users)
 }
 
 async fn sync_latest_author_posts(
     bsky: &Bsky,
+    config: &Config,
     user: &User,
     start_time: &DateTime<Utc>,
-) -> Vec<NewPost> {
+    end_time: &DateTime<Utc>,
+) -> Result<Vec<NewPost>, Error> {
     let mut options = FeedListOptions {
         actor: user.did.clone(),
         limit: Some(100),
@@ -131,70 +657,45 @@ async fn sync_latest_author_posts(
         filter: Some(FeedFilter::PostsWithReplies),
     };
 
-    let like_weight = 1;
-    let reply_weight = 5;
-    let repost_weight = 3;
-    let quote_weight = 4;
-
-    let end_time = Utc::now() - Duration::hours(END_TIME);
-
     let mut posts: Vec<NewPost> = Vec::new();
+    let mut seen_uris: HashSet<String> = HashSet::new();
     let mut is_out_range = false;
     loop {
-        let res = bsky.get_author_feed(&options).await;
-
-        if res.is_err() {
-            println!("Error during syncing users: {}", res.err().unwrap());
-            break;
-        }
-
-        let res = res.unwrap();
+        let res = bsky.get_author_feed(&options).await?;
         options.cursor = res.cursor;
 
         for feed in res.feed {
-            if feed.post.cid.is_none() {
+            let order = feed_order(&feed);
+            if order == FeedOrder::Repost {
                 continue;
             }
 
-            if let Some(reason) = feed.reason {
-                if reason.reason_type == ReasonType::Repost {
-                    continue;
-                }
+            // A pinned post shows up again at its chronological place
+            let uri = feed.post.uri.clone();
+            if !seen_uris.insert(uri.clone()) {
+                continue;
             }
 
-            let record_created_at = &feed.post.record.as_ref().unwrap().created_at;
-            let created_at = DateTime::parse_from_rfc3339(&record_created_at)
-                .unwrap()
-                .with_timezone(&Utc);
+            let post = match feed_to_post(config, user, feed) {
+                Ok(post) => post,
+                Err(error) => {
+                    println!("Skipping post {}: {}", uri, error);
+                    continue;
+                }
+            };
 
-            if !(*start_time >= created_at && created_at >= end_time) {
-                is_out_range = true;
-                break;
+            let created_at = post.created_at.and_utc();
+            if created_at > *start_time {
+                continue;
+            }
+            if created_at < *end_time {
+                // Only chronologically ordered items prove the rest of the feed is older
+                if order == FeedOrder::Chronological {
+                    is_out_range = true;
+                    break;
+                }
+                continue;
             }
-
-            let like_count = feed.post.like_count.unwrap();
-            let reply_count = feed.post.reply_count.unwrap();
-            let repost_count = feed.post.repost_count.unwrap();
-            let quote_count = feed.post.quote_count.unwrap();
-
-            let like_points = like_count * like_weight;
-            let reply_points = reply_count * reply_weight;
-            let repost_points = repost_count * repost_weight;
-            let quote_points = quote_count * quote_weight;
-
-            let total_points = like_points + reply_points + repost_points + quote_points;
-
-            let post = NewPost {
-                created_at: created_at.naive_utc(),
-                uri: feed.post.uri,
-                cid: feed.post.cid.unwrap(),
-                user_id: user.id,
-                reply_count,
-                repost_count,
-                like_count,
-                quote_count,
-                total_points,
-            };
 
             posts.push(post);
         }
@@ -204,68 +705,813 @@ async fn sync_latest_author_posts(
         }
     }
 
-    posts
+    Ok(posts)
+}
+
+/// Place of an author feed item relative to the reverse chronological order
+#[derive(PartialEq)]
+enum FeedOrder {
+    /// Author's own post at its chronological place
+    Chronological,
+    /// Pinned post shown on top of the feed regardless of its age
+    Pinned,
+    /// Repost ordered by the time of reposting, not of the post
+    Repost,
+    /// Item with a reason we don't know, can't be trusted to be in order
+    Unordered,
+}
+
+fn feed_order(feed: &Feed) -> FeedOrder {
+    match feed.reason.as_ref().map(|reason| &reason.reason_type) {
+        None => FeedOrder::Chronological,
+        Some(ReasonType::Pin) => FeedOrder::Pinned,
+        Some(ReasonType::Repost) => FeedOrder::Repost,
+        Some(ReasonType::Unknown(_)) => FeedOrder::Unordered,
+    }
+}
+
+fn feed_to_post(config: &Config, user: &User, feed: Feed) -> Result<NewPost, BskyError> {
+    let metrics = post_metrics(config, &feed.post);
+
+    let cid = feed
+        .post
+        .cid
+        .ok_or_else(|| BskyError::DataError(String::from("post has no cid")))?;
+    let record = feed
+        .post
+        .record
+        .ok_or_else(|| BskyError::DataError(String::from("post has no record")))?;
+
+    let created_at = DateTime::parse_from_rfc3339(&record.created_at)
+        .map_err(|err| {
+            BskyError::DataError(format!(
+                "invalid createdAt \"{}\": {}",
+                record.created_at, err
+            ))
+        })?
+        .with_timezone(&Utc);
+
+    let root_uri = match record.reply {
+        Some(reply) => reply.root.uri,
+        None => feed.post.uri.clone(),
+    };
+
+    Ok(NewPost {
+        created_at: created_at.naive_utc(),
+        uri: feed.post.uri,
+        cid,
+        user_id: user.id,
+        reply_count: metrics.reply_count,
+        repost_count: metrics.repost_count,
+        like_count: metrics.like_count,
+        quote_count: metrics.quote_count,
+        total_points: metrics.total_points,
+        root_uri: Some(root_uri),
+    })
+}
+
+fn post_metrics(config: &Config, post: &BskyPost) -> PostMetrics {
+    let counters = Counters {
+        likes: post.like_count.unwrap_or(0),
+        replies: post.reply_count.unwrap_or(0),
+        reposts: post.repost_count.unwrap_or(0),
+        quotes: post.quote_count.unwrap_or(0),
+    };
+
+    PostMetrics {
+        reply_count: counters.replies,
+        repost_count: counters.reposts,
+        like_count: counters.likes,
+        quote_count: counters.quotes,
+        total_points: Weights::from_config(&config.scoring).points(&counters),
+        refreshed_at: Some(clock::now().naive_utc()),
+        missed_refreshes: 0,
+    }
+}
+
+/// Post of a thread being published, either loaded from the publication log or
+/// built in memory for a dry run.
+struct ThreadEntry {
+    id: Option<i32>,
+    position: i32,
+    text: String,
+    users: Vec<User>,
+    post: Option<PostRef>,
+    record: Option<PostRef>,
+    /// Record key of an entry sent by an earlier run, the post may exist
+    reserved_rkey: Option<String>,
 }
 
-pub async fn post_top_ten(bsky: &Bsky) {
-    let bench = Bench::start("Posting thread");
-    let posts_with_users = match queries::get_top_ten_posts_with_users() {
-        Ok(res) => Some(res),
-        Err(error) => {
-            println!("Error during sync with database: {}", error);
+impl From<(Publication, Option<User>)> for ThreadEntry {
+    fn from((publication, user): (Publication, Option<User>)) -> Self {
+        let record = if publication.status == PublicationStatus::Published.as_str() {
+            publication
+                .record_uri
+                .zip(publication.record_cid)
+                .map(|(uri, cid)| PostRef { uri, cid })
+        } else {
+            None
+        };
+        let reserved_rkey = if publication.status == PublicationStatus::Published.as_str() {
             None
+        } else {
+            publication.record_rkey
+        };
+
+        ThreadEntry {
+            id: Some(publication.id),
+            position: publication.position,
+            text: publication.text,
+            users: user.into_iter().collect(),
+            post: publication
+                .post_uri
+                .zip(publication.post_cid)
+                .map(|(uri, cid)| PostRef { uri, cid }),
+            record,
+            reserved_rkey,
         }
+    }
+}
+
+pub async fn post_top_ten(bsky: &Bsky, config: &Config) -> Result<(), Error> {
+    let Some(_guard) = PublishGuard::acquire(RankingKind::Daily.as_str()) else {
+        return Ok(());
+    };
+
+    // Posts that reach the window while the thread is posted wait for the next ranking
+    let cutoff = ranking_cutoff(config);
+
+    post_ranking(bsky, config, RankingKind::Daily).await?;
+
+    // Shadow runs never touch the publication log or the collected posts
+    if bsky.is_dry_run() {
+        return Ok(());
+    }
+
+    let bench = Bench::start("Archiving ranked posts");
+    queries::archive_posts_before(cutoff)?;
+    bench.end();
+
+    apply_retention(config)?;
+
+    Ok(())
+}
+
+/// Deletes archived posts and rankings older than their retention
+fn apply_retention(config: &Config) -> Result<(), DieselError> {
+    let now = clock::now();
+
+    if config.archive.posts_retention_days > 0 {
+        let before = (now - Duration::days(config.archive.posts_retention_days)).naive_utc();
+        let deleted = queries::delete_archived_posts_before(before)?;
+        println!("Deleted {} archived posts", deleted);
+    }
+
+    if config.archive.rankings_retention_days > 0 {
+        let before = (now - Duration::days(config.archive.rankings_retention_days)).date_naive();
+        let deleted = queries::delete_rankings_before(before)?;
+        println!("Deleted {} archived rankings", deleted);
+    }
+
+    Ok(())
+}
+
+/// Publishes the short list of posts gaining engagement faster than usual.
+/// Posts stay tracked for the daily ranking.
+pub async fn post_trending(bsky: &Bsky, config: &Config) -> Result<(), Error> {
+    let Some(_guard) = PublishGuard::acquire(RankingKind::Trending.as_str()) else {
+        return Ok(());
     };
 
-    let posts_with_users = if posts_with_users.is_some() {
-        posts_with_users.unwrap()
-    } else {
-        return;
+    post_ranking(bsky, config, RankingKind::Trending).await
+}
+
+/// Publishes the weekly or the monthly best of. Ranked posts are already
+/// archived and stay there.
+pub async fn post_best_of(bsky: &Bsky, config: &Config, kind: RankingKind) -> Result<(), Error> {
+    let Some(_guard) = PublishGuard::acquire(kind.as_str()) else {
+        return Ok(());
     };
 
-    if posts_with_users.len() == 0 {
-        let bench = Bench::start("Dropping all posts");
-        if let Err(error) = queries::drop_all_posts() {
-            println!("Error during sync with database: {}", error);
+    post_ranking(bsky, config, kind).await
+}
+
+async fn post_ranking(bsky: &Bsky, config: &Config, kind: RankingKind) -> Result<(), Error> {
+    let bench = Bench::start(format!("Posting {} thread", kind.as_str()).as_str());
+
+    let thread = load_thread(config, kind, bsky.is_dry_run())?;
+
+    if let Some((ranking_id, entries)) = thread {
+        publish_thread(bsky, config, &ranking_id, entries).await?;
+        bench.end();
+    }
+
+    Ok(())
+}
+
+/// Loads the unfinished publication of the kind or ranks a new one. A dry run
+/// keeps the new ranking in memory instead of storing it in the publication
+/// log.
+fn load_thread(
+    config: &Config,
+    kind: RankingKind,
+    dry_run: bool,
+) -> Result<Option<(String, Vec<ThreadEntry>)>, DieselError> {
+    let ranking_id = match queries::get_unfinished_ranking_id(kind.as_str())? {
+        Some(ranking_id) => {
+            println!("Resuming unfinished publication {}", ranking_id);
+            ranking_id
+        }
+        None if dry_run => {
+            let ranking = rank(config, kind)?;
+            if ranking.is_empty() {
+                return Ok(None);
+            }
+
+            let ranking_id = format!("{}-dry-run", kind.as_str());
+            let entries = ranking_entries(config, kind, &ranking_id, &ranking);
+            let users: Vec<&User> = ranking.iter().map(|candidate| &candidate.user).collect();
+
+            return Ok(Some((ranking_id, draft_thread(entries, &users))));
         }
+        None => match prepare_publication(config, kind)? {
+            Some(ranking_id) => ranking_id,
+            None => return Ok(None),
+        },
+    };
+
+    let entries = queries::get_publication_entries(&ranking_id)?
+        .into_iter()
+        .map(ThreadEntry::from)
+        .collect();
+
+    Ok(Some((ranking_id, entries)))
+}
+
+/// Thread entries of a publication kept in memory
+fn draft_thread(entries: Vec<NewPublication>, users: &[&User]) -> Vec<ThreadEntry> {
+    entries
+        .into_iter()
+        .map(|entry| ThreadEntry {
+            id: None,
+            position: entry.position,
+            users: users
+                .iter()
+                .filter(|user| Some(user.id) == entry.user_id)
+                .map(|user| (*user).clone())
+                .take(1)
+                .collect(),
+            text: entry.text,
+            post: entry
+                .post_uri
+                .zip(entry.post_cid)
+                .map(|(uri, cid)| PostRef { uri, cid }),
+            record: None,
+            reserved_rkey: None,
+        })
+        .collect()
+}
+
+/// Publishes the authors most often in the daily top over the last
+/// `leaderboard.days`, every entry mentions its author.
+pub async fn post_authors_leaderboard(bsky: &Bsky, config: &Config) -> Result<(), Error> {
+    let Some(_guard) = PublishGuard::acquire(LEADERBOARD_KIND) else {
+        return Ok(());
+    };
+
+    let bench = Bench::start("Posting authors leaderboard thread");
+
+    let thread = load_leaderboard_thread(config, bsky.is_dry_run())?;
+
+    if let Some((ranking_id, entries)) = thread {
+        publish_thread(bsky, config, &ranking_id, entries).await?;
         bench.end();
-        return;
     }
 
-    for (post, user) in posts_with_users.iter() {
-        let display_name = user.display_name.clone().unwrap_or_default();
-        let message = format!(
-            "#Топ10 {}",
-            if display_name.is_empty() {
-                user.handle.clone()
+    Ok(())
+}
+
+/// Same as `load_thread` for the authors leaderboard
+fn load_leaderboard_thread(
+    config: &Config,
+    dry_run: bool,
+) -> Result<Option<(String, Vec<ThreadEntry>)>, DieselError> {
+    let ranking_id = match queries::get_unfinished_ranking_id(LEADERBOARD_KIND)? {
+        Some(ranking_id) => {
+            println!("Resuming unfinished publication {}", ranking_id);
+            ranking_id
+        }
+        None => {
+            let ranking_id = if dry_run {
+                format!("{}-dry-run", LEADERBOARD_KIND)
             } else {
-                display_name
+                format!("{}-{}", LEADERBOARD_KIND, Utc::now().format("%Y%m%d%H%M%S"))
+            };
+
+            let leaderboard = leaderboard(config)?;
+            if leaderboard.is_empty() {
+                return Ok(None);
             }
-        );
 
-        let facets = parse_facets_with_users(&message, &vec![user.to_owned()]);
+            let entries = leaderboard_entries(config, &ranking_id, &leaderboard);
+            if dry_run {
+                let users: Vec<&User> = leaderboard.iter().map(|stats| &stats.user).collect();
+                return Ok(Some((ranking_id, draft_thread(entries, &users))));
+            }
 
-        let embed = Embed {
-            embed_type: EmbedType::Record,
-            record: PostRef {
-                uri: post.uri.clone(),
-                cid: post.cid.clone(),
-            },
+            queries::create_publication(&entries)?;
+            ranking_id
+        }
+    };
+
+    let entries = queries::get_publication_entries(&ranking_id)?
+        .into_iter()
+        .map(ThreadEntry::from)
+        .collect();
+
+    Ok(Some((ranking_id, entries)))
+}
+
+/// Top authors of the daily rankings of the last `leaderboard.days`
+pub fn leaderboard(config: &Config) -> Result<Vec<AuthorStats>, DieselError> {
+    let mut leaderboard = queries::get_author_leaderboard(leaderboard_since(config))?;
+    leaderboard.truncate(config.leaderboard.size);
+
+    Ok(leaderboard)
+}
+
+fn leaderboard_since(config: &Config) -> NaiveDate {
+    (clock::now() - Duration::days(config.leaderboard.days)).date_naive()
+}
+
+/// Builds the thread of the authors leaderboard, one entry per author
+pub fn leaderboard_entries(
+    config: &Config,
+    ranking_id: &str,
+    leaderboard: &[AuthorStats],
+) -> Vec<NewPublication> {
+    let header = template::render(
+        &config.leaderboard.header_template,
+        &[
+            (
+                "start",
+                &leaderboard_since(config).format("%d.%m.%Y").to_string(),
+            ),
+            ("end", &clock::now().format("%d.%m.%Y").to_string()),
+        ],
+    );
+
+    let mut entries = vec![NewPublication {
+        ranking_id: ranking_id.to_string(),
+        position: 0,
+        user_id: None,
+        text: header,
+        post_uri: None,
+        post_cid: None,
+        status: PublicationStatus::Pending.as_str().to_string(),
+    }];
+
+    for (position, stats) in leaderboard.iter().enumerate() {
+        let position = position as i32 + 1;
+        let name = match stats.user.display_name.as_deref() {
+            Some(name) if !name.is_empty() => name,
+            _ => &stats.user.handle,
+        };
+        let render_entry = |name: &str| {
+            template::render(
+                &config.leaderboard.entry_template,
+                &[
+                    ("position", &position.to_string()),
+                    ("handle", &stats.user.handle),
+                    ("name", name),
+                    ("appearances", &stats.appearances.to_string()),
+                    ("best_position", &stats.best_position.to_string()),
+                    ("streak", &stats.current_streak.to_string()),
+                    ("points", &stats.total_points.to_string()),
+                ],
+            )
         };
 
-        let _ = bsky
-            .create_post(message, Some(facets), None, Some(embed))
+        // Like ranking entries, the name is shortened first to fit the limit
+        let mut text = render_entry(name);
+        let excess = template::graphemes_count(&text).saturating_sub(MAX_POST_GRAPHEMES);
+        if excess > 0 {
+            if let Some(available) = template::graphemes_count(name)
+                .checked_sub(excess)
+                .filter(|available| *available > 0)
+            {
+                text = render_entry(&template::truncate(name, available));
+            }
+            text = template::truncate(&text, MAX_POST_GRAPHEMES);
+        }
+
+        entries.push(NewPublication {
+            ranking_id: ranking_id.to_string(),
+            position,
+            user_id: Some(stats.user.id),
+            text,
+            post_uri: None,
+            post_cid: None,
+            status: PublicationStatus::Pending.as_str().to_string(),
+        });
+    }
+
+    entries
+}
+
+/// Posts the pending entries as a reply chain under the first one. An error
+/// leaves the remaining entries pending so the thread can be resumed later.
+async fn publish_thread(
+    bsky: &Bsky,
+    config: &Config,
+    ranking_id: &str,
+    entries: Vec<ThreadEntry>,
+) -> Result<(), Error> {
+    let dry_run = bsky.is_dry_run();
+
+    let mut root: Option<PostRef> = None;
+    let mut parent: Option<PostRef> = None;
+    let mut is_first_post = true;
+
+    for entry in entries {
+        if let Some(record) = entry.record {
+            root.get_or_insert(record.clone());
+            parent = Some(record);
+            continue;
+        }
+
+        // An earlier run stopped between posting the entry and saving the
+        // result, or lost the response of a post that went through
+        if let (Some(id), Some(rkey), false) = (entry.id, &entry.reserved_rkey, dry_run) {
+            if let Some(record) = bsky.get_own_post(rkey).await? {
+                println!(
+                    "Entry {} of {} was already posted",
+                    entry.position, ranking_id
+                );
+                queries::mark_publication_published(id, &record.uri, &record.cid)?;
+                root.get_or_insert(record.clone());
+                parent = Some(record);
+                continue;
+            }
+        }
+
+        if !is_first_post && !dry_run {
+            tokio::time::sleep(std::time::Duration::from_secs(config.publish.delay_secs)).await;
+        }
+        is_first_post = false;
+
+        // The record key is saved before posting, so a resumed run can find
+        // out whether the post was created
+        let rkey = match (entry.id, dry_run) {
+            (Some(id), false) => {
+                let rkey = entry.reserved_rkey.clone().unwrap_or_else(new_tid);
+                let stale_before =
+                    (clock::now() - Duration::minutes(PUBLICATION_LEASE_MINUTES)).naive_utc();
+                match queries::claim_publication(id, &rkey, stale_before)? {
+                    Some(rkey) => Some(rkey),
+                    None => {
+                        return Err(Error::Claimed(format!(
+                            "Entry {} of {} is being posted by another run",
+                            entry.position, ranking_id
+                        )))
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let facets = parse_facets_with_users(&entry.text, &entry.users);
+
+        let embed = entry.post.map(|record| Embed {
+            embed_type: EmbedType::Record,
+            record,
+        });
+
+        let reply = root
+            .clone()
+            .zip(parent.clone())
+            .map(|(root, parent)| Reply { root, parent });
+
+        let record = match bsky
+            .create_post(entry.text, Some(facets), reply, embed, rkey.as_deref())
             .await
-            .unwrap();
+        {
+            Ok(record) => record,
+            Err(error) => {
+                println!(
+                    "Error during posting entry {} of {}: {}",
+                    entry.position, ranking_id, error
+                );
+                // A refused post can be retried right away, an unanswered one
+                // stays claimed until it is checked or the lease runs out
+                if let (Some(id), true) = (entry.id, error.is_rejection()) {
+                    queries::release_publication(id)?;
+                }
+                return Err(error.into());
+            }
+        };
+
+        if let (Some(id), false) = (entry.id, dry_run) {
+            queries::mark_publication_published(id, &record.uri, &record.cid)?;
+        }
 
-        // 5 minute delay
-        tokio::time::sleep(std::time::Duration::from_secs(300)).await;
+        root.get_or_insert(record.clone());
+        parent = Some(record);
     }
-    bench.end();
 
-    let bench = Bench::start("Dropping all posts");
-    if let Err(error) = queries::drop_all_posts() {
-        println!("Error during sync with database: {}", error);
+    Ok(())
+}
+
+pub fn rank(config: &Config, kind: RankingKind) -> Result<Vec<Candidate>, DieselError> {
+    match kind {
+        RankingKind::Daily => rank_top_ten(config),
+        RankingKind::Trending => rank_trending(config),
+        RankingKind::Weekly | RankingKind::Monthly => rank_period(config, kind),
     }
-    bench.end();
+}
+
+/// Current top ten of the collected posts, scored by the configured strategy
+/// and sorted by score.
+pub fn rank_top_ten(config: &Config) -> Result<Vec<Candidate>, DieselError> {
+    let posts_with_users = queries::get_ranking_candidates(ranking_cutoff(config))?;
+
+    let mut strategy = scoring::strategy_from_config(&config.scoring);
+    let candidates = score_candidates(
+        strategy.as_mut(),
+        posts_with_users,
+        clock::now().naive_utc(),
+    );
+
+    Ok(select_diverse(
+        candidates,
+        config.ranking.size,
+        config.ranking.max_posts_per_author,
+    ))
+}
+
+/// Best of the posts stored over the period of the weekly or monthly ranking,
+/// archived ones included. Every post is scored as of the age the daily
+/// ranking saw it at, so older posts of the period don't lose to time decay.
+pub fn rank_period(config: &Config, kind: RankingKind) -> Result<Vec<Candidate>, DieselError> {
+    let (period, since, before) = ranking_period(config, kind);
+    let posts_with_users = queries::get_period_candidates(since, before)?;
+
+    let ranked_age = Duration::hours(config.collection.window_start_hours);
+    let mut strategy = scoring::strategy_from_config(&config.scoring);
+    let candidates = score_candidates_at(strategy.as_mut(), posts_with_users, |post| {
+        post.created_at + ranked_age
+    });
+
+    if period.rank_authors {
+        let mut authors = rank_authors(candidates);
+        authors.truncate(period.size);
+        return Ok(authors);
+    }
+
+    Ok(select_diverse(
+        candidates,
+        period.size,
+        config.ranking.max_posts_per_author,
+    ))
+}
+
+/// Settings and the ranked time range of the weekly or monthly ranking. The
+/// range ends where the daily ranking stops.
+fn ranking_period(
+    config: &Config,
+    kind: RankingKind,
+) -> (&PeriodRankingConfig, NaiveDateTime, NaiveDateTime) {
+    let (period, days) = match kind {
+        RankingKind::Monthly => (&config.monthly, MONTHLY_PERIOD_DAYS),
+        _ => (&config.weekly, WEEKLY_PERIOD_DAYS),
+    };
+    let before = ranking_cutoff(config);
+
+    (period, before - Duration::days(days), before)
+}
+
+/// Young posts whose engagement grows fastest compared to their authors'
+/// usual pace, leaving out posts that were already trending.
+pub fn rank_trending(config: &Config) -> Result<Vec<Candidate>, DieselError> {
+    let now = clock::now();
+    let since = now - Duration::hours(config.collection.window_end_hours);
+
+    let history = queries::get_metrics_history(since.naive_utc())?;
+    let published_uris = queries::get_published_post_uris(RankingKind::Trending)?;
+
+    let candidates = trending::score_trending(
+        history,
+        now.naive_utc(),
+        Duration::hours(config.trending.max_age_hours),
+        Duration::hours(config.trending.lookback_hours),
+        config.scoring.baseline_prior_posts,
+    )
+    .into_iter()
+    .filter(|candidate| !published_uris.contains(&candidate.post.uri))
+    .collect();
+
+    Ok(select_diverse(
+        candidates,
+        config.trending.size,
+        config.ranking.max_posts_per_author,
+    ))
+}
+
+/// Posts younger than the collection window are still gaining reactions and
+/// wait for a later ranking.
+fn ranking_cutoff(config: &Config) -> NaiveDateTime {
+    (clock::now() - Duration::hours(config.collection.window_start_hours)).naive_utc()
+}
+
+/// Builds the thread of a ranking. Position 0 is the thread root, entries
+/// follow in rank order.
+pub fn ranking_entries(
+    config: &Config,
+    kind: RankingKind,
+    ranking_id: &str,
+    ranking: &[Candidate],
+) -> Vec<NewPublication> {
+    let (header, message_prefix) = match kind {
+        RankingKind::Daily => {
+            let ranking_date = (clock::now()
+                - Duration::hours(config.collection.window_start_hours))
+            .format("%d.%m.%Y")
+            .to_string();
+            let header =
+                template::render(&config.publish.header_template, &[("date", &ranking_date)]);
+
+            (header, &config.publish.message_prefix)
+        }
+        RankingKind::Trending => {
+            let now = clock::now();
+            let header = template::render(
+                &config.trending.header_template,
+                &[
+                    ("date", &now.format("%d.%m.%Y").to_string()),
+                    ("time", &now.format("%H:%M").to_string()),
+                ],
+            );
+
+            (header, &config.trending.message_prefix)
+        }
+        RankingKind::Weekly | RankingKind::Monthly => {
+            let (period, since, before) = ranking_period(config, kind);
+            let header = template::render(
+                &period.header_template,
+                &[
+                    ("start", &since.format("%d.%m.%Y").to_string()),
+                    ("end", &before.format("%d.%m.%Y").to_string()),
+                ],
+            );
+
+            (header, &period.message_prefix)
+        }
+    };
+
+    let mut entries = vec![NewPublication {
+        ranking_id: ranking_id.to_string(),
+        position: 0,
+        user_id: None,
+        text: header,
+        post_uri: None,
+        post_cid: None,
+        status: PublicationStatus::Pending.as_str().to_string(),
+    }];
+
+    let size = ranking.len().to_string();
+    for (position, Candidate { post, user, .. }) in ranking.iter().enumerate() {
+        let display_name = user.display_name.clone().unwrap_or_default();
+        let name = if display_name.is_empty() {
+            user.handle.clone()
+        } else {
+            display_name
+        };
+
+        let breakdown = config.publish.breakdown_template.as_ref().map(|breakdown| {
+            template::render(
+                breakdown,
+                &[
+                    ("position", &(position + 1).to_string()),
+                    ("size", &size),
+                    ("likes", &post.like_count.to_string()),
+                    ("replies", &post.reply_count.to_string()),
+                    ("reposts", &post.repost_count.to_string()),
+                    ("quotes", &post.quote_count.to_string()),
+                    ("points", &post.total_points.to_string()),
+                ],
+            )
+        });
+        let text = entry_text(message_prefix, &name, breakdown.as_deref());
+
+        entries.push(NewPublication {
+            ranking_id: ranking_id.to_string(),
+            position: position as i32 + 1,
+            user_id: Some(user.id),
+            text,
+            post_uri: Some(post.uri.clone()),
+            post_cid: Some(post.cid.clone()),
+            status: PublicationStatus::Pending.as_str().to_string(),
+        });
+    }
+
+    entries
+}
+
+/// `<prefix> <name>` with the breakdown on the next line, kept within the
+/// post length limit. The name is shortened first, the breakdown is left out
+/// only when even one character of the name doesn't fit next to it.
+fn entry_text(prefix: &str, name: &str, breakdown: Option<&str>) -> String {
+    let breakdown = breakdown
+        .map(|breakdown| format!("\n{}", breakdown))
+        .unwrap_or_default();
+    let reserved = template::graphemes_count(prefix) + 1 + template::graphemes_count(&breakdown);
+
+    match MAX_POST_GRAPHEMES.checked_sub(reserved) {
+        Some(available) if available > 0 => format!(
+            "{} {}{}",
+            prefix,
+            template::truncate(name, available),
+            breakdown
+        ),
+        _ => template::truncate(&format!("{} {}", prefix, name), MAX_POST_GRAPHEMES),
+    }
+}
+
+/// Stores the current ranking of the kind as a pending publication so an
+/// interrupted run can be resumed.
+pub fn prepare_publication(
+    config: &Config,
+    kind: RankingKind,
+) -> Result<Option<String>, DieselError> {
+    let ranking = rank(config, kind)?;
+
+    if ranking.is_empty() {
+        return Ok(None);
+    }
+
+    let ranking_id = format!("{}-{}", kind.as_str(), Utc::now().format("%Y%m%d%H%M%S"));
+    let entries = ranking_entries(config, kind, &ranking_id, &ranking);
+
+    let (new_ranking, archived_entries) = archived_ranking(config, kind, &ranking_id, &ranking);
+    queries::create_archived_publication(&entries, &new_ranking, archived_entries)?;
+
+    Ok(Some(ranking_id))
+}
+
+/// Copy of the ranking with its scores kept for later audits, the publication
+/// log only knows what was posted.
+fn archived_ranking(
+    config: &Config,
+    kind: RankingKind,
+    ranking_id: &str,
+    ranking: &[Candidate],
+) -> (NewRanking, Vec<NewRankingEntry>) {
+    let (ranked_date, strategy) = match kind {
+        RankingKind::Daily => (
+            (clock::now() - Duration::hours(config.collection.window_start_hours)).date_naive(),
+            config.scoring.strategy.to_string(),
+        ),
+        RankingKind::Trending => (clock::now().date_naive(), String::from("velocity")),
+        RankingKind::Weekly | RankingKind::Monthly => {
+            let (period, _, before) = ranking_period(config, kind);
+            let strategy = if period.rank_authors {
+                format!("{} authors", config.scoring.strategy)
+            } else {
+                config.scoring.strategy.to_string()
+            };
+
+            (before.date(), strategy)
+        }
+    };
+
+    let new_ranking = NewRanking {
+        key: ranking_id.to_string(),
+        kind: kind.as_str().to_string(),
+        ranked_date,
+        strategy,
+        config_hash: config.fingerprint(),
+    };
+
+    let entries = ranking
+        .iter()
+        .enumerate()
+        .map(
+            |(position, Candidate { post, user, score })| NewRankingEntry {
+                ranking_id: 0,
+                position: position as i32 + 1,
+                post_id: Some(post.id),
+                user_id: Some(user.id),
+                post_uri: post.uri.clone(),
+                post_created_at: post.created_at,
+                reply_count: post.reply_count,
+                repost_count: post.repost_count,
+                like_count: post.like_count,
+                quote_count: post.quote_count,
+                total_points: post.total_points,
+                score: *score,
+            },
+        )
+        .collect();
+
+    (new_ranking, entries)
 }
@@ -0,0 +1,717 @@
+use super::{
+    mock_server::{BOT_DID, BOT_PASSWORD},
+    TestEnv,
+};
+use crate::{
+    config::Config,
+    database::{
+        models::{NewPost, Post, PostMetrics, User},
+        queries::{self, AuthorStats},
+    },
+    services::bsky::{recording::Replayer, Bsky, MAX_POST_GRAPHEMES},
+    tasks,
+    utils::clock,
+};
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+const ALICE_REPLY: &str = "at://did:plc:alice/app.bsky.feed.post/reply";
+const ALICE_THREAD: &str = "at://did:plc:alice/app.bsky.feed.post/thread";
+const BOB_QUOTED: &str = "at://did:plc:bob/app.bsky.feed.post/quoted";
+const BOB_QUIET: &str = "at://did:plc:bob/app.bsky.feed.post/quiet";
+const CAROL_SHARED: &str = "at://did:plc:carol/app.bsky.feed.post/shared";
+
+/// Facet of the `#Топ10` tag starting the text
+fn top10_tag() -> Value {
+    json!({
+        "index": { "byteStart": 0, "byteEnd": "#Топ10".len() },
+        "features": [{ "$type": "app.bsky.richtext.facet#tag", "tag": "Топ10" }],
+    })
+}
+
+/// Facet of a tag in the middle of the text
+fn tag_facet(text: &str, tag: &str) -> Value {
+    let start = text.find(&format!("#{}", tag)).unwrap();
+    json!({
+        "index": { "byteStart": start, "byteEnd": start + 1 + tag.len() },
+        "features": [{ "$type": "app.bsky.richtext.facet#tag", "tag": tag }],
+    })
+}
+
+/// Reference of the post created by the `createRecord` payload
+fn created_ref(record: &Value) -> Value {
+    let rkey = record["rkey"].as_str().expect("record without rkey");
+    json!({
+        "uri": format!("at://{}/app.bsky.feed.post/{}", BOT_DID, rkey),
+        "cid": format!("cid-{}", rkey),
+    })
+}
+
+/// `createRecord` payload of a thread entry replying to the `parent`-th
+/// record, `createdAt` and `rkey` are left out
+fn thread_entry(records: &[Value], text: &str, parent: usize, quoted: (&str, &str)) -> Value {
+    json!({
+        "repo": BOT_DID,
+        "collection": "app.bsky.feed.post",
+        "record": {
+            "$type": "app.bsky.feed.post",
+            "text": text,
+            "facets": [top10_tag()],
+            "reply": {
+                "root": created_ref(&records[0]),
+                "parent": created_ref(&records[parent - 1]),
+            },
+            "embed": {
+                "$type": "app.bsky.embed.record",
+                "record": { "uri": quoted.0, "cid": quoted.1 },
+            },
+        },
+        "lang": ["ua"],
+        "validate": true,
+    })
+}
+
+fn points_by_uri(posts: &[(Post, User)]) -> HashSet<(String, i32)> {
+    posts
+        .iter()
+        .map(|(post, _)| (post.uri.clone(), post.total_points))
+        .collect()
+}
+
+/// Payloads without the generated `createdAt` and `rkey`
+fn without_generated_fields(mut records: Vec<Value>) -> Vec<Value> {
+    for record in records.iter_mut() {
+        let created_at = record["record"]
+            .as_object_mut()
+            .and_then(|record| record.remove("createdAt"));
+        assert!(created_at.is_some(), "record without createdAt: {}", record);
+        record.as_object_mut().unwrap().remove("rkey");
+    }
+    records
+}
+
+/// Payloads of a thread posted for the community fixture
+fn community_thread(records: &[Value]) -> Vec<Value> {
+    let header = format!(
+        "#Топ10 постів за {}",
+        (Utc::now() - Duration::hours(24)).format("%d.%m.%Y")
+    );
+    vec![
+        json!({
+            "repo": BOT_DID,
+            "collection": "app.bsky.feed.post",
+            "record": {
+                "$type": "app.bsky.feed.post",
+                "text": header,
+                "facets": [top10_tag()],
+            },
+            "lang": ["ua"],
+            "validate": true,
+        }),
+        // A single post of the thread, the best one
+        thread_entry(records, "#Топ10 Alice", 1, (ALICE_REPLY, "cid-alice-reply")),
+        thread_entry(
+            records,
+            "#Топ10 Carol",
+            2,
+            (CAROL_SHARED, "cid-carol-shared"),
+        ),
+        // Without a display name the handle is used
+        thread_entry(
+            records,
+            "#Топ10 bob.test",
+            3,
+            (BOB_QUOTED, "cid-bob-quoted"),
+        ),
+    ]
+}
+
+#[tokio::test]
+async fn sync_users_stores_follows_with_audience() {
+    let env = TestEnv::start("community.json").await;
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+
+    let users = queries::get_users_list(10, 0).unwrap();
+    assert_eq!(users.len(), 3);
+
+    let alice = queries::get_user_by_handle("alice.test").unwrap().unwrap();
+    assert_eq!(alice.did, "did:plc:alice");
+    assert_eq!(alice.display_name.as_deref(), Some("Alice"));
+    assert_eq!(alice.followers_count, Some(1200));
+    assert_eq!(alice.posts_count, Some(340));
+
+    let bob = queries::get_user_by_handle("bob.test").unwrap().unwrap();
+    assert_eq!(bob.display_name, None);
+    assert_eq!(bob.followers_count, Some(40));
+}
+
+#[tokio::test]
+async fn sync_users_posts_collects_the_window() {
+    let env = TestEnv::start("community.json").await;
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+
+    let posts = queries::get_ranking_candidates(Utc::now().naive_utc()).unwrap();
+    let uris: HashSet<&str> = posts.iter().map(|(post, _)| post.uri.as_str()).collect();
+    // Pinned, too young, reposted and too old posts are left out
+    assert_eq!(
+        uris,
+        HashSet::from([
+            ALICE_REPLY,
+            ALICE_THREAD,
+            BOB_QUOTED,
+            BOB_QUIET,
+            CAROL_SHARED
+        ])
+    );
+
+    let (reply, author) = posts
+        .iter()
+        .find(|(post, _)| post.uri == ALICE_REPLY)
+        .unwrap();
+    assert_eq!(author.handle, "alice.test");
+    assert_eq!(reply.root_uri.as_deref(), Some(ALICE_THREAD));
+    assert_eq!(reply.total_points, 30);
+
+    let (thread, _) = posts
+        .iter()
+        .find(|(post, _)| post.uri == ALICE_THREAD)
+        .unwrap();
+    assert_eq!(thread.root_uri.as_deref(), Some(ALICE_THREAD));
+    assert_eq!(
+        (
+            thread.like_count,
+            thread.reply_count,
+            thread.repost_count,
+            thread.quote_count,
+            thread.total_points
+        ),
+        (10, 2, 1, 0, 23)
+    );
+
+    // Collecting the same window again updates the posts instead of duplicating them
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    let posts = queries::get_ranking_candidates(Utc::now().naive_utc()).unwrap();
+    assert_eq!(posts.len(), 5);
+}
+
+#[tokio::test]
+async fn archived_posts_keep_their_counters() {
+    let env = TestEnv::start("community.json").await;
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    let now = Utc::now().naive_utc();
+    queries::archive_posts_before(now).unwrap();
+
+    let archived_thread = || {
+        queries::get_period_candidates(now - Duration::days(7), now)
+            .unwrap()
+            .into_iter()
+            .map(|(post, _)| post)
+            .find(|post| post.uri == ALICE_THREAD)
+            .unwrap()
+    };
+    // Counters as they were when the post was ranked
+    let ranked = PostMetrics {
+        reply_count: 0,
+        repost_count: 0,
+        like_count: 7,
+        quote_count: 0,
+        total_points: 7,
+        refreshed_at: None,
+        missed_refreshes: 0,
+    };
+    queries::update_post_metrics(&[(archived_thread().id, ranked)]).unwrap();
+
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+
+    let thread = archived_thread();
+    assert!(thread.archived_at.is_some());
+    assert_eq!((thread.like_count, thread.total_points), (7, 7));
+}
+
+#[tokio::test]
+async fn refresh_drops_posts_missing_repeatedly() {
+    let env = TestEnv::start("community.json").await;
+    const DELETED: &str = "at://did:plc:alice/app.bsky.feed.post/deleted";
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    let alice = queries::get_user_by_handle("alice.test").unwrap().unwrap();
+    queries::save_posts(&vec![NewPost {
+        created_at: (Utc::now() - Duration::hours(24)).naive_utc(),
+        user_id: alice.id,
+        uri: String::from(DELETED),
+        cid: String::from("cid-alice-deleted"),
+        reply_count: 0,
+        repost_count: 0,
+        like_count: 100,
+        quote_count: 0,
+        total_points: 100,
+        root_uri: None,
+    }])
+    .unwrap();
+
+    let candidate_uris = || -> HashSet<String> {
+        queries::get_ranking_candidates(Utc::now().naive_utc())
+            .unwrap()
+            .into_iter()
+            .map(|(post, _)| post.uri)
+            .collect()
+    };
+    assert!(candidate_uris().contains(DELETED));
+
+    // A missing post leaves the rankings but is kept in case it comes back
+    tasks::refresh_posts_metrics(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    assert_eq!(candidate_uris().len(), 5);
+    assert!(!candidate_uris().contains(DELETED));
+    assert_eq!(queries::get_stats().unwrap().posts, 6);
+
+    for _ in 0..2 {
+        tasks::refresh_posts_metrics(&env.bsky, &env.config)
+            .await
+            .unwrap();
+    }
+    assert_eq!(queries::get_stats().unwrap().posts, 5);
+}
+
+#[tokio::test]
+async fn post_top_ten_publishes_the_thread() {
+    let env = TestEnv::start("community.json").await;
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    tasks::post_top_ten(&env.bsky, &env.config).await.unwrap();
+
+    let records = env.server.records();
+    assert_eq!(
+        without_generated_fields(records.clone()),
+        community_thread(&records)
+    );
+    assert_eq!(env.server.calls("createSession"), 1);
+    assert_eq!(env.server.calls("getRecord"), 0);
+
+    assert_eq!(queries::get_unfinished_ranking_id("daily").unwrap(), None);
+
+    let stats = queries::get_stats().unwrap();
+    assert_eq!(stats.posts, 0);
+    assert_eq!(stats.archived_posts, 5);
+    assert_eq!(stats.rankings, 1);
+}
+
+#[tokio::test]
+async fn post_top_ten_resumes_without_reposting() {
+    let env = TestEnv::start("community.json").await;
+    // The first entry is created but the bot never learns about it
+    env.server.lose_record_response(2);
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    assert!(tasks::post_top_ten(&env.bsky, &env.config).await.is_err());
+    let ranking_id = queries::get_unfinished_ranking_id("daily")
+        .unwrap()
+        .unwrap();
+
+    // The failed run holds the entry until its lease runs out, then it is
+    // taken over with the same record key
+    let (in_flight, _) = queries::get_publication_entries(&ranking_id)
+        .unwrap()
+        .into_iter()
+        .find(|(entry, _)| entry.status == "in_flight")
+        .unwrap();
+    let rkey = in_flight.record_rkey.clone();
+    assert!(rkey.is_some());
+    let now = Utc::now().naive_utc();
+    assert_eq!(
+        queries::claim_publication(in_flight.id, "other", now - Duration::minutes(10)).unwrap(),
+        None
+    );
+    assert_eq!(
+        queries::claim_publication(in_flight.id, "other", now + Duration::minutes(1)).unwrap(),
+        rkey
+    );
+
+    tasks::post_top_ten(&env.bsky, &env.config).await.unwrap();
+
+    let records = env.server.records();
+    assert_eq!(
+        without_generated_fields(records.clone()),
+        community_thread(&records)
+    );
+    assert_eq!(env.server.calls("getRecord"), 1);
+    assert_eq!(queries::get_unfinished_ranking_id("daily").unwrap(), None);
+}
+
+#[tokio::test]
+async fn refused_entry_is_released_for_the_next_run() {
+    let env = TestEnv::start("community.json").await;
+    env.server.reject_record(3);
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    assert!(tasks::post_top_ten(&env.bsky, &env.config).await.is_err());
+    let ranking_id = queries::get_unfinished_ranking_id("daily")
+        .unwrap()
+        .unwrap();
+
+    // Nothing was created, the entry is free again with its record key
+    let (refused, _) = queries::get_publication_entries(&ranking_id)
+        .unwrap()
+        .into_iter()
+        .find(|(entry, _)| entry.position == 2)
+        .unwrap();
+    assert_eq!(refused.status, "pending");
+    assert!(refused.record_rkey.is_some());
+
+    // A run that finds the entry claimed by another one fails without posting
+    let stale_before = (Utc::now() - Duration::minutes(10)).naive_utc();
+    queries::claim_publication(refused.id, "other", stale_before).unwrap();
+    assert!(matches!(
+        tasks::post_top_ten(&env.bsky, &env.config).await,
+        Err(tasks::Error::Claimed(_))
+    ));
+    assert_eq!(env.server.records().len(), 2);
+
+    queries::release_publication(refused.id).unwrap();
+    tasks::post_top_ten(&env.bsky, &env.config).await.unwrap();
+
+    let records = env.server.records();
+    assert_eq!(
+        without_generated_fields(records.clone()),
+        community_thread(&records)
+    );
+    assert_eq!(queries::get_unfinished_ranking_id("daily").unwrap(), None);
+}
+
+#[tokio::test]
+async fn concurrent_runs_post_the_thread_once() {
+    let env = TestEnv::start("community.json").await;
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    // Like the scheduler resuming a thread while the cron job starts it
+    let (first, second) = tokio::join!(
+        tasks::post_top_ten(&env.bsky, &env.config),
+        tasks::post_top_ten(&env.bsky, &env.config)
+    );
+    first.unwrap();
+    second.unwrap();
+
+    let records = env.server.records();
+    assert_eq!(
+        without_generated_fields(records.clone()),
+        community_thread(&records)
+    );
+    assert_eq!(queries::get_stats().unwrap().rankings, 1);
+}
+
+#[tokio::test]
+async fn post_top_ten_adds_points_breakdown() {
+    let mut env = TestEnv::start("community.json").await;
+    env.config.publish.breakdown_template = Some(String::from(
+        "{position}/{size} · ❤️ {likes} 💬 {replies} 🔁 {reposts} 💭 {quotes} · {points} #бали",
+    ));
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    tasks::post_top_ten(&env.bsky, &env.config).await.unwrap();
+
+    let entries: Vec<(Value, Value)> = env.server.records()[1..]
+        .iter()
+        .map(|record| {
+            (
+                record["record"]["text"].clone(),
+                record["record"]["facets"].clone(),
+            )
+        })
+        .collect();
+
+    let texts = [
+        "#Топ10 Alice\n1/3 · ❤️ 30 💬 0 🔁 0 💭 0 · 30 #бали",
+        "#Топ10 Carol\n2/3 · ❤️ 12 💬 0 🔁 2 💭 0 · 18 #бали",
+        "#Топ10 bob.test\n3/3 · ❤️ 5 💬 0 🔁 0 💭 1 · 9 #бали",
+    ];
+    let expected: Vec<(Value, Value)> = texts
+        .iter()
+        .map(|text| (json!(text), json!([top10_tag(), tag_facet(text, "бали")])))
+        .collect();
+    assert_eq!(entries, expected);
+}
+
+#[tokio::test]
+async fn long_entries_are_shortened_to_the_post_limit() {
+    let mut env = TestEnv::start("community.json").await;
+    // Leaves 3 graphemes for the name next to the prefix and the breakdown
+    let prefix = format!("#Топ10 {}", "я".repeat(285));
+    env.config.publish.message_prefix = prefix.clone();
+    env.config.publish.breakdown_template = Some(String::from("{position}/{size}"));
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    tasks::post_top_ten(&env.bsky, &env.config).await.unwrap();
+
+    let texts: Vec<String> = env.server.records()[1..]
+        .iter()
+        .map(|record| record["record"]["text"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        texts,
+        vec![
+            format!("{} Al…\n1/3", prefix),
+            format!("{} Ca…\n2/3", prefix),
+            format!("{} bo…\n3/3", prefix),
+        ]
+    );
+    for text in &texts {
+        assert_eq!(text.graphemes(true).count(), 300);
+    }
+}
+
+#[tokio::test]
+async fn expired_access_token_is_refreshed() {
+    let env = TestEnv::start("community.json").await;
+
+    env.bsky.authenticate().await.unwrap();
+    env.server.expire_access_token();
+
+    let record = env
+        .bsky
+        .create_post(String::from("Hello"), None, None, None, None)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        record.uri,
+        format!("at://{}/app.bsky.feed.post/record-1", BOT_DID)
+    );
+    assert_eq!(env.server.calls("refreshSession"), 1);
+    assert_eq!(env.server.calls("createRecord"), 2);
+    assert_eq!(
+        without_generated_fields(env.server.records()),
+        vec![json!({
+            "repo": BOT_DID,
+            "collection": "app.bsky.feed.post",
+            "record": { "$type": "app.bsky.feed.post", "text": "Hello" },
+            "lang": ["ua"],
+            "validate": true,
+        })]
+    );
+}
+
+#[tokio::test]
+async fn overlapping_jobs_share_one_session() {
+    let env = TestEnv::start("community.json").await;
+    let other_job = env.bsky.clone();
+
+    // Like cron jobs firing at the same minute
+    let (first, second, third) = tokio::join!(
+        env.bsky.ensure_session(),
+        other_job.ensure_session(),
+        tasks::sync_users(&other_job, &env.config)
+    );
+    first.unwrap();
+    second.unwrap();
+    third.unwrap();
+    env.bsky.ensure_session().await.unwrap();
+
+    assert_eq!(env.server.calls("createSession"), 1);
+}
+
+#[tokio::test]
+async fn session_stays_on_the_entryway() {
+    let env = TestEnv::start("community.json").await;
+
+    // Nothing listens on the PDS, only posts would go there
+    let bsky = Bsky::builder()
+        .entryway(&env.server.url)
+        .pds(Some("http://127.0.0.1:9"))
+        .appview(&env.server.url)
+        .build();
+    bsky.authenticate().await.unwrap();
+    bsky.refresh().await.unwrap();
+
+    assert_eq!(env.server.calls("createSession"), 1);
+    assert_eq!(env.server.calls("refreshSession"), 1);
+}
+
+#[test]
+fn leaderboard_entries_keep_names_verbatim_within_the_limit() {
+    let mut config = Config::default();
+    config.leaderboard.entry_template = String::from("{position}. {name} (@{handle}) {points}");
+    let stats = |display_name: &str| AuthorStats {
+        user: User {
+            id: 1,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+            did: String::from("did:plc:alice"),
+            handle: String::from("alice.bsky.social"),
+            display_name: Some(display_name.to_string()),
+            followers_count: None,
+            posts_count: None,
+        },
+        appearances: 3,
+        best_position: 1,
+        current_streak: 2,
+        total_points: 42,
+    };
+
+    let long_name = format!("{{points}} {}", "Я".repeat(400));
+    let entries = tasks::leaderboard_entries(
+        &config,
+        "authors-test",
+        &[stats("{handle} {points}"), stats(&long_name)],
+    );
+
+    assert_eq!(
+        entries[1].text,
+        "1. {handle} {points} (@alice.bsky.social) 42"
+    );
+    let text = &entries[2].text;
+    assert!(text.starts_with("2. {points} ЯЯЯ"), "{}", text);
+    assert!(text.ends_with("Я… (@alice.bsky.social) 42"), "{}", text);
+    assert_eq!(text.graphemes(true).count(), MAX_POST_GRAPHEMES);
+}
+
+#[tokio::test]
+async fn recorded_firehose_is_counted_once() {
+    let env = TestEnv::start("community.json").await;
+    let stream = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/fixtures/jetstream.jsonl");
+
+    tasks::sync_users(&env.bsky, &env.config).await.unwrap();
+    tasks::sync_users_posts(&env.bsky, &env.config)
+        .await
+        .unwrap();
+    let counters = || -> HashMap<String, (i32, i32, i32, i32, i32)> {
+        let now = Utc::now().naive_utc();
+        queries::get_period_candidates(now - Duration::days(7), now)
+            .unwrap()
+            .into_iter()
+            .map(|(post, _)| {
+                let counters = (
+                    post.like_count,
+                    post.reply_count,
+                    post.repost_count,
+                    post.quote_count,
+                    post.total_points,
+                );
+                (post.uri, counters)
+            })
+            .collect()
+    };
+    let before = counters();
+
+    tasks::replay_firehose(&env.config, &stream).unwrap();
+    assert_eq!(counters(), before);
+    assert_eq!(queries::get_firehose_cursor("jetstream").unwrap(), None);
+
+    tasks::apply_firehose(&env.config, &stream).unwrap();
+
+    // A like and a reply of the thread, a repost and a quote. The broken line,
+    // the like of an unfollowed author and the deletion count for nothing
+    let scoring = &env.config.scoring;
+    let mut expected = before.clone();
+    let thread = expected.get_mut(ALICE_THREAD).unwrap();
+    thread.0 += 1;
+    thread.1 += 1;
+    thread.4 += scoring.like_weight + scoring.reply_weight;
+    let shared = expected.get_mut(CAROL_SHARED).unwrap();
+    shared.2 += 1;
+    shared.4 += scoring.repost_weight;
+    let quoted = expected.get_mut(BOB_QUOTED).unwrap();
+    quoted.3 += 1;
+    quoted.4 += scoring.quote_weight;
+    assert_eq!(counters(), expected);
+    assert_eq!(
+        queries::get_firehose_cursor("jetstream").unwrap(),
+        Some(1760000000000007)
+    );
+
+    // Events up to the stored cursor were counted already
+    tasks::apply_firehose(&env.config, &stream).unwrap();
+    assert_eq!(counters(), expected);
+}
+
+#[tokio::test]
+async fn recorded_fixtures_replay_offline() {
+    let env = TestEnv::start("community.json").await;
+    let fixtures_dir = tempfile::tempdir().unwrap();
+
+    let recording = Bsky::builder()
+        .entryway(&env.server.url)
+        .appview(&env.server.url)
+        .record(fixtures_dir.path().to_path_buf())
+        .build();
+    recording.authenticate().await.unwrap();
+    tasks::sync_users(&recording, &env.config).await.unwrap();
+    tasks::sync_users_posts(&recording, &env.config)
+        .await
+        .unwrap();
+    let recorded = queries::get_ranking_candidates(Utc::now().naive_utc()).unwrap();
+
+    let session = fs::read_to_string(fixtures_dir.path().join("00001-createSession.json")).unwrap();
+    for secret in [BOT_PASSWORD, "access-1", "refresh-1"] {
+        assert!(!session.contains(secret), "{} is not redacted", secret);
+    }
+    assert!(session.contains(BOT_DID));
+
+    // Nothing listens there, every response has to come from the fixtures
+    let replayer = Replayer::load(fixtures_dir.path()).unwrap();
+    let recorded_at = replayer.recorded_at().unwrap();
+    assert!(recorded_at <= Utc::now() && recorded_at > Utc::now() - Duration::minutes(1));
+    clock::pin(Some(recorded_at));
+
+    let replaying = Bsky::builder()
+        .entryway("http://127.0.0.1:9")
+        .appview("http://127.0.0.1:9")
+        .replay(replayer)
+        .build();
+    replaying.authenticate().await.unwrap();
+    tasks::sync_users(&replaying, &env.config).await.unwrap();
+    tasks::sync_users_posts(&replaying, &env.config)
+        .await
+        .unwrap();
+
+    let replayed = queries::get_ranking_candidates(clock::now().naive_utc()).unwrap();
+    assert_eq!(clock::now(), recorded_at);
+    assert_eq!(points_by_uri(&replayed), points_by_uri(&recorded));
+
+    let unknown = replaying.get_posts(&[String::from(BOB_QUIET)]).await;
+    assert!(
+        unknown.is_err(),
+        "request missing from the fixtures is served"
+    );
+    clock::pin(None);
+}
@@ -0,0 +1,465 @@
+use axum::{
+    extract::{RawQuery, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{Duration, SecondsFormat, Utc};
+use regex::{Captures, Regex};
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, LazyLock, Mutex},
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+pub const BOT_DID: &str = "did:plc:bot";
+pub const BOT_HANDLE: &str = "bot.test";
+pub const BOT_PASSWORD: &str = "app-password";
+
+static HOURS_AGO_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""@hours_ago:([0-9.]+)""#).unwrap());
+
+/// Accounts and author feeds served by the mock server
+pub struct Fixtures {
+    /// Account whose follows are served by `getFollows`
+    pub provider: String,
+    /// Detailed profiles of the follows
+    pub follows: Vec<Value>,
+    /// Feed items by author DID, newest first
+    pub feeds: HashMap<String, Vec<Value>>,
+}
+
+impl Fixtures {
+    /// Loads `src/tests/fixtures/<name>`. Strings like `"@hours_ago:24.5"`
+    /// become timestamps that many hours before now, so posts land in the
+    /// collection window whenever the tests run.
+    pub fn load(name: &str) -> Fixtures {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/tests/fixtures")
+            .join(name);
+        let text = fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("Unable to read {}: {}", path.display(), err));
+
+        let now = Utc::now();
+        let text = HOURS_AGO_REGEX.replace_all(&text, |captures: &Captures| {
+            let hours: f64 = captures[1].parse().unwrap();
+            let time = now - Duration::seconds((hours * 3600.0) as i64);
+            format!("\"{}\"", time.to_rfc3339_opts(SecondsFormat::Millis, true))
+        });
+
+        let mut fixtures: Value = serde_json::from_str(&text)
+            .unwrap_or_else(|err| panic!("Invalid fixture {}: {}", path.display(), err));
+
+        Fixtures {
+            provider: fixtures["provider"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            follows: serde_json::from_value(fixtures["follows"].take()).unwrap_or_default(),
+            feeds: serde_json::from_value(fixtures["feeds"].take()).unwrap_or_default(),
+        }
+    }
+
+    fn post(&self, uri: &str) -> Option<&Value> {
+        self.feeds
+            .values()
+            .flatten()
+            .map(|item| &item["post"])
+            .find(|post| post["uri"] == uri)
+    }
+}
+
+struct MockState {
+    fixtures: Fixtures,
+    url: String,
+    issued_sessions: usize,
+    access_jwt: Option<String>,
+    refresh_jwt: Option<String>,
+    /// `createRecord` payloads in the order they were received
+    records: Vec<Value>,
+    /// Created posts by record key
+    repo: HashMap<String, Value>,
+    /// Number of the `createRecord` call whose response is lost after the
+    /// record is created
+    lost_record_response: Option<usize>,
+    /// Number of the `createRecord` call refused without creating the record
+    rejected_record: Option<usize>,
+    /// Number of calls by XRPC method
+    calls: HashMap<String, usize>,
+}
+
+type SharedState = Arc<Mutex<MockState>>;
+type XrpcResponse = (StatusCode, Json<Value>);
+
+/// In-process XRPC server implementing the endpoints the bot uses. It serves
+/// as entryway, PDS (through the DID document of the session) and AppView.
+pub struct MockServer {
+    pub url: String,
+    state: SharedState,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start(fixtures: Fixtures) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let state = Arc::new(Mutex::new(MockState {
+            fixtures,
+            url: url.clone(),
+            issued_sessions: 0,
+            access_jwt: None,
+            refresh_jwt: None,
+            records: Vec::new(),
+            repo: HashMap::new(),
+            lost_record_response: None,
+            rejected_record: None,
+            calls: HashMap::new(),
+        }));
+
+        let router = Router::new()
+            .route(
+                "/xrpc/com.atproto.server.createSession",
+                post(create_session),
+            )
+            .route(
+                "/xrpc/com.atproto.server.refreshSession",
+                post(refresh_session),
+            )
+            .route("/xrpc/com.atproto.repo.createRecord", post(create_record))
+            .route("/xrpc/com.atproto.repo.getRecord", get(get_record))
+            .route("/xrpc/app.bsky.graph.getFollows", get(get_follows))
+            .route("/xrpc/app.bsky.actor.getProfiles", get(get_profiles))
+            .route("/xrpc/app.bsky.feed.getAuthorFeed", get(get_author_feed))
+            .route("/xrpc/app.bsky.feed.getPosts", get(get_posts))
+            .with_state(state.clone());
+
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        MockServer { url, state, handle }
+    }
+
+    /// `createRecord` payloads received so far
+    pub fn records(&self) -> Vec<Value> {
+        self.state.lock().unwrap().records.clone()
+    }
+
+    pub fn calls(&self, method: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.calls.get(method).copied().unwrap_or_default()
+    }
+
+    /// Creates the record of the `number`-th `createRecord` call but answers
+    /// it with a server error, as if the connection dropped.
+    pub fn lose_record_response(&self, number: usize) {
+        self.state.lock().unwrap().lost_record_response = Some(number);
+    }
+
+    /// Refuses the `number`-th `createRecord` call without creating the record
+    pub fn reject_record(&self, number: usize) {
+        self.state.lock().unwrap().rejected_record = Some(number);
+    }
+
+    /// Invalidates the current access token, the next authorized call fails
+    /// with `ExpiredToken` until the session is refreshed.
+    pub fn expire_access_token(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.access_jwt = Some(String::from("rotated-by-the-server"));
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+fn error(status: StatusCode, error: &str, message: &str) -> XrpcResponse {
+    (status, Json(json!({ "error": error, "message": message })))
+}
+
+fn record_call(state: &mut MockState, method: &str) {
+    *state.calls.entry(method.to_string()).or_default() += 1;
+}
+
+fn query_pairs(query: Option<String>) -> Vec<(String, String)> {
+    let url = Url::parse(&format!("http://mock/?{}", query.unwrap_or_default())).unwrap();
+    url.query_pairs().into_owned().collect()
+}
+
+fn query_values(pairs: &[(String, String)], key: &str) -> Vec<String> {
+    pairs
+        .iter()
+        .filter(|(name, _)| name == key)
+        .map(|(_, value)| value.clone())
+        .collect()
+}
+
+fn query_value(pairs: &[(String, String)], key: &str) -> Option<String> {
+    query_values(pairs, key).into_iter().next()
+}
+
+/// Items of the page starting at the `cursor` offset, with the cursor of the
+/// next page
+fn page(items: &[Value], pairs: &[(String, String)]) -> (Vec<Value>, Option<String>) {
+    let limit: usize = query_value(pairs, "limit")
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(50);
+    let offset: usize = query_value(pairs, "cursor")
+        .and_then(|cursor| cursor.parse().ok())
+        .unwrap_or(0);
+
+    let end = (offset + limit).min(items.len());
+    let page = items.get(offset..end).unwrap_or_default().to_vec();
+    let cursor = (end < items.len()).then(|| end.to_string());
+
+    (page, cursor)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+fn issue_session(state: &mut MockState) -> XrpcResponse {
+    state.issued_sessions += 1;
+    let access_jwt = format!("access-{}", state.issued_sessions);
+    let refresh_jwt = format!("refresh-{}", state.issued_sessions);
+    state.access_jwt = Some(access_jwt.clone());
+    state.refresh_jwt = Some(refresh_jwt.clone());
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "accessJwt": access_jwt,
+            "refreshJwt": refresh_jwt,
+            "handle": BOT_HANDLE,
+            "did": BOT_DID,
+            "didDoc": {
+                "id": BOT_DID,
+                "service": [
+                    {
+                        "id": "#atproto_labeler",
+                        "type": "AtprotoLabeler",
+                        "serviceEndpoint": { "uri": state.url },
+                    },
+                    {
+                        "id": "#atproto_pds",
+                        "type": "AtprotoPersonalDataServer",
+                        "serviceEndpoint": state.url,
+                    },
+                ],
+            },
+        })),
+    )
+}
+
+async fn create_session(State(state): State<SharedState>, Json(body): Json<Value>) -> XrpcResponse {
+    let mut state = state.lock().unwrap();
+    record_call(&mut state, "createSession");
+
+    if body["identifier"] != BOT_HANDLE || body["password"] != BOT_PASSWORD {
+        return error(
+            StatusCode::UNAUTHORIZED,
+            "AuthenticationRequired",
+            "Invalid identifier or password",
+        );
+    }
+
+    issue_session(&mut state)
+}
+
+async fn refresh_session(State(state): State<SharedState>, headers: HeaderMap) -> XrpcResponse {
+    let mut state = state.lock().unwrap();
+    record_call(&mut state, "refreshSession");
+
+    if bearer_token(&headers) != state.refresh_jwt.as_deref() {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "ExpiredToken",
+            "Token has been revoked",
+        );
+    }
+
+    issue_session(&mut state)
+}
+
+async fn create_record(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> XrpcResponse {
+    let mut state = state.lock().unwrap();
+    record_call(&mut state, "createRecord");
+
+    if bearer_token(&headers).is_none() || bearer_token(&headers) != state.access_jwt.as_deref() {
+        return error(StatusCode::BAD_REQUEST, "ExpiredToken", "Token has expired");
+    }
+    if body["repo"] != BOT_DID {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Repo does not match the session",
+        );
+    }
+
+    if state.rejected_record == Some(state.calls["createRecord"]) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Record is invalid",
+        );
+    }
+
+    let rkey = match body["rkey"].as_str() {
+        Some(rkey) => rkey.to_string(),
+        None => format!("record-{}", state.records.len() + 1),
+    };
+    if state.repo.contains_key(&rkey) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Record already exists",
+        );
+    }
+
+    let record = json!({
+        "uri": format!("at://{}/app.bsky.feed.post/{}", BOT_DID, rkey),
+        "cid": format!("cid-{}", rkey),
+        "value": body["record"],
+    });
+    state.repo.insert(rkey, record.clone());
+    state.records.push(body);
+
+    if state.lost_record_response == Some(state.records.len()) {
+        return error(
+            StatusCode::BAD_GATEWAY,
+            "UpstreamFailure",
+            "Connection dropped",
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({ "uri": record["uri"], "cid": record["cid"] })),
+    )
+}
+
+async fn get_record(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> XrpcResponse {
+    let mut state = state.lock().unwrap();
+    record_call(&mut state, "getRecord");
+
+    if bearer_token(&headers).is_none() || bearer_token(&headers) != state.access_jwt.as_deref() {
+        return error(StatusCode::BAD_REQUEST, "ExpiredToken", "Token has expired");
+    }
+
+    let pairs = query_pairs(query);
+    if query_value(&pairs, "repo").as_deref() != Some(BOT_DID) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Repo does not match the session",
+        );
+    }
+
+    let rkey = query_value(&pairs, "rkey").unwrap_or_default();
+    match state.repo.get(&rkey) {
+        Some(record) => (StatusCode::OK, Json(record.clone())),
+        None => error(
+            StatusCode::BAD_REQUEST,
+            "RecordNotFound",
+            "Could not locate record",
+        ),
+    }
+}
+
+async fn get_follows(State(state): State<SharedState>, RawQuery(query): RawQuery) -> XrpcResponse {
+    let mut state = state.lock().unwrap();
+    record_call(&mut state, "getFollows");
+
+    let pairs = query_pairs(query);
+    if query_value(&pairs, "actor").as_deref() != Some(state.fixtures.provider.as_str()) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Profile not found",
+        );
+    }
+
+    let (follows, cursor) = page(&state.fixtures.follows, &pairs);
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "subject": { "did": "did:plc:provider", "handle": state.fixtures.provider },
+            "follows": follows,
+            "cursor": cursor,
+        })),
+    )
+}
+
+async fn get_profiles(State(state): State<SharedState>, RawQuery(query): RawQuery) -> XrpcResponse {
+    let mut state = state.lock().unwrap();
+    record_call(&mut state, "getProfiles");
+
+    let actors = query_values(&query_pairs(query), "actors");
+    let profiles: Vec<&Value> = state
+        .fixtures
+        .follows
+        .iter()
+        .filter(|profile| actors.iter().any(|actor| profile["did"] == actor.as_str()))
+        .collect();
+
+    (StatusCode::OK, Json(json!({ "profiles": profiles })))
+}
+
+async fn get_author_feed(
+    State(state): State<SharedState>,
+    RawQuery(query): RawQuery,
+) -> XrpcResponse {
+    let mut state = state.lock().unwrap();
+    record_call(&mut state, "getAuthorFeed");
+
+    let pairs = query_pairs(query);
+    let actor = query_value(&pairs, "actor").unwrap_or_default();
+    let Some(feed) = state.fixtures.feeds.get(&actor) else {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Profile not found",
+        );
+    };
+
+    let (feed, cursor) = page(feed, &pairs);
+
+    (
+        StatusCode::OK,
+        Json(json!({ "feed": feed, "cursor": cursor })),
+    )
+}
+
+async fn get_posts(State(state): State<SharedState>, RawQuery(query): RawQuery) -> XrpcResponse {
+    let mut state = state.lock().unwrap();
+    record_call(&mut state, "getPosts");
+
+    let uris = query_values(&query_pairs(query), "uris");
+    let posts: Vec<&Value> = uris
+        .iter()
+        .filter_map(|uri| state.fixtures.post(uri))
+        .collect();
+
+    (StatusCode::OK, Json(json!({ "posts": posts })))
+}
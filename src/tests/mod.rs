@@ -0,0 +1,58 @@
+use crate::{config::Config, database, services::bsky::Bsky, utils::clock};
+use mock_server::{Fixtures, MockServer, BOT_HANDLE, BOT_PASSWORD};
+use std::env;
+use tempfile::TempDir;
+use tokio::sync::{Mutex, MutexGuard};
+
+mod e2e;
+mod mock_server;
+
+/// The database, the credentials and the clock come from the process environment, so
+/// tests touching them run one at a time.
+static ENV_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Mock server, client and migrated temporary database of one test
+pub struct TestEnv {
+    pub server: MockServer,
+    pub bsky: Bsky,
+    pub config: Config,
+    _database_dir: TempDir,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl TestEnv {
+    pub async fn start(fixture: &str) -> TestEnv {
+        let guard = ENV_LOCK.lock().await;
+
+        let database_dir = tempfile::tempdir().unwrap();
+        env::set_var("DATABASE_URL", database_dir.path().join("bot.db"));
+        env::set_var("BLUESKY_HANDLE", BOT_HANDLE);
+        env::set_var("BLUESKY_PASSWORD", BOT_PASSWORD);
+        database::embeded_migrations::migrate();
+        // A test that failed while replaying may have left the clock pinned
+        clock::pin(None);
+
+        let fixtures = Fixtures::load(fixture);
+        let mut config = Config::default();
+        config.provider.account = fixtures.provider.clone();
+        config.publish.delay_secs = 0;
+
+        let server = MockServer::start(fixtures).await;
+        config.bsky.entryway = server.url.clone();
+        config.bsky.appview = server.url.clone();
+
+        let bsky = Bsky::builder()
+            .entryway(&config.bsky.entryway)
+            .appview(&config.bsky.appview)
+            .lang(&config.publish.lang)
+            .build();
+
+        TestEnv {
+            server,
+            bsky,
+            config,
+            _database_dir: database_dir,
+            _guard: guard,
+        }
+    }
+}
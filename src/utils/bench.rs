@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use std::time::{Duration, Instant};
 
 pub struct Bench {
@@ -21,7 +21,7 @@ impl Bench {
         }
     }
 
-    pub fn end(&self) -> () {
+    pub fn end(&self) {
         let duration = self.start.elapsed();
 
         let time = get_time_from_duration(duration);
@@ -35,7 +35,7 @@ impl Bench {
         )
     }
 
-    pub fn end_with(&self, message: &str) -> () {
+    pub fn end_with(&self, message: &str) {
         let duration = self.start.elapsed();
 
         let time = get_time_from_duration(duration);
@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+use std::sync::RwLock;
+
+/// Time the clock is frozen at, if any
+static PINNED: RwLock<Option<DateTime<Utc>>> = RwLock::new(None);
+
+/// Current time of the collection windows and the rankings. Replaying
+/// recorded fixtures pins it to the time of the recording.
+pub fn now() -> DateTime<Utc> {
+    PINNED.read().unwrap().unwrap_or_else(Utc::now)
+}
+
+/// Freezes the clock at `time`, `None` lets it run again
+pub fn pin(time: Option<DateTime<Utc>>) {
+    *PINNED.write().unwrap() = time;
+}
@@ -1 +1,3 @@
 pub mod bench;
+pub mod clock;
+pub mod template;
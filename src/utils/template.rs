@@ -0,0 +1,91 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Replaces every `{key}` placeholder of the template with its value in one
+/// pass, so placeholders inside the values are left as they are. Unknown
+/// placeholders are kept.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+
+        let value = placeholder.find('}').and_then(|end| {
+            values
+                .iter()
+                .find(|(key, _)| *key == &placeholder[1..end])
+                .map(|(_, value)| (*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                text.push_str(value);
+                rest = &placeholder[end + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = &placeholder[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+
+    text
+}
+
+/// Number of user-perceived characters, the unit of the post length limit
+pub fn graphemes_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Shortens the text to `max` graphemes, ending it with `…` when something
+/// was cut off.
+pub fn truncate(text: &str, max: usize) -> String {
+    if graphemes_count(text) <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+
+    let kept: String = text.graphemes(true).take(max - 1).collect();
+    format!("{}…", kept.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_replaces_known_placeholders() {
+        assert_eq!(
+            render(
+                "{position}. {name} {unknown} {{points}",
+                &[("position", "1"), ("name", "Alice"), ("points", "7")]
+            ),
+            "1. Alice {unknown} {7"
+        );
+    }
+
+    #[test]
+    fn render_leaves_placeholders_in_values() {
+        assert_eq!(
+            render(
+                "{name} @{handle} {points}",
+                &[
+                    ("name", "{points} {handle}"),
+                    ("handle", "alice.bsky.social"),
+                    ("points", "7")
+                ]
+            ),
+            "{points} {handle} @alice.bsky.social 7"
+        );
+    }
+
+    #[test]
+    fn truncate_counts_graphemes() {
+        assert_eq!(truncate("Олена 👩‍👩‍👧", 7), "Олена 👩‍👩‍👧");
+        assert_eq!(truncate("Олена Петренко", 7), "Олена…");
+        assert_eq!(truncate("Олена", 0), "");
+    }
+}